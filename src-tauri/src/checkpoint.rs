@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use crate::nostr_types::{AuthPolicy, SubscribeOpts};
+
+/// A frontend subscription as it was requested, so it can be replayed
+#[derive(Debug, Clone)]
+pub struct SavedSubscription {
+    pub filters: Vec<serde_json::Value>,
+    pub opts: Option<SubscribeOpts>,
+}
+
+/// Relay set, auth policies and active subscriptions, kept outside the nostr thread's
+/// thread-locals so they survive a panic restart
+#[derive(Debug, Default)]
+pub struct Checkpoint {
+    relays: Vec<String>,
+    subscriptions: HashMap<String, SavedSubscription>,
+    auth_policies: HashMap<String, AuthPolicy>,
+    search_relays: Vec<String>,
+}
+
+impl Checkpoint {
+    pub fn add_relay(&mut self, url: &str) {
+        if !self.relays.iter().any(|r| r == url) {
+            self.relays.push(url.to_string());
+        }
+    }
+
+    pub fn remove_relay(&mut self, url: &str) {
+        self.relays.retain(|r| r != url);
+    }
+
+    pub fn add_subscription(&mut self, id: &str, filters: &[serde_json::Value], opts: &Option<SubscribeOpts>) {
+        self.subscriptions.insert(id.to_string(), SavedSubscription {
+            filters: filters.to_vec(),
+            opts: opts.clone(),
+        });
+    }
+
+    pub fn remove_subscription(&mut self, id: &str) {
+        self.subscriptions.remove(id);
+    }
+
+    /// Drop subscriptions that are no longer active (e.g. closed after EOSE)
+    pub fn retain_subscriptions(&mut self, mut is_active: impl FnMut(&str) -> bool) {
+        self.subscriptions.retain(|id, _| is_active(id));
+    }
+
+    pub fn subscription(&self, id: &str) -> Option<SavedSubscription> {
+        self.subscriptions.get(id).cloned()
+    }
+
+    pub fn set_auth_policy(&mut self, relay: &str, policy: AuthPolicy) {
+        self.auth_policies.insert(relay.to_string(), policy);
+    }
+
+    pub fn auth_policies(&self) -> Vec<(String, AuthPolicy)> {
+        self.auth_policies
+            .iter()
+            .map(|(relay, policy)| (relay.clone(), *policy))
+            .collect()
+    }
+
+    pub fn set_search_relays(&mut self, urls: &[String]) {
+        self.search_relays = urls.to_vec();
+    }
+
+    pub fn search_relays(&self) -> Vec<String> {
+        self.search_relays.clone()
+    }
+
+    pub fn subscription_count(&self) -> usize {
+        self.subscriptions.len()
+    }
+
+    pub fn relays(&self) -> Vec<String> {
+        self.relays.clone()
+    }
+
+    pub fn subscriptions(&self) -> Vec<(String, SavedSubscription)> {
+        self.subscriptions
+            .iter()
+            .map(|(id, sub)| (id.clone(), sub.clone()))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SharedCheckpoint(Arc<Mutex<Checkpoint>>);
+
+impl SharedCheckpoint {
+    /// Lock the checkpoint, ignoring poisoning - a panic mid-update still
+    /// leaves a usable relay and subscription list
+    pub fn lock(&self) -> MutexGuard<'_, Checkpoint> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
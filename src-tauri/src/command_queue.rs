@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tauri::ipc::Channel;
+use crate::nostr_types::{CachedEvents, CommandQueueStats, NostrRequest};
+
+/// A frontend command with the time it entered the queue
+pub struct QueuedCommand {
+    pub request: NostrRequest,
+    pub queued_at: Instant,
+    /// Per-subscription channel for batched cached events (nostr_subscribe)
+    pub channel: Option<Channel<CachedEvents>>,
+}
+
+impl QueuedCommand {
+    pub fn new(request: NostrRequest) -> Self {
+        Self { request, queued_at: Instant::now(), channel: None }
+    }
+
+    pub fn with_channel(request: NostrRequest, channel: Channel<CachedEvents>) -> Self {
+        Self { request, queued_at: Instant::now(), channel: Some(channel) }
+    }
+}
+
+/// Queue depth and command latency, shared between the IPC handler and the nostr thread
+#[derive(Default)]
+pub struct CommandMetrics {
+    depth: AtomicUsize,
+    max_depth: AtomicUsize,
+    processed: AtomicU64,
+    total_latency_us: AtomicU64,
+    max_latency_us: AtomicU64,
+}
+
+impl CommandMetrics {
+    pub fn queued(&self) {
+        let depth = self.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        self.max_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    pub fn dequeued(&self, latency: Duration) {
+        let latency_us = latency.as_micros() as u64;
+        self.depth.fetch_sub(1, Ordering::Relaxed);
+        self.processed.fetch_add(1, Ordering::Relaxed);
+        self.total_latency_us.fetch_add(latency_us, Ordering::Relaxed);
+        self.max_latency_us.fetch_max(latency_us, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> CommandQueueStats {
+        let processed = self.processed.load(Ordering::Relaxed);
+        let total_latency_us = self.total_latency_us.load(Ordering::Relaxed);
+        let avg_latency_ms = if processed > 0 {
+            total_latency_us as f64 / processed as f64 / 1000.0
+        } else {
+            0.0
+        };
+        CommandQueueStats {
+            queue_depth: self.depth.load(Ordering::Relaxed),
+            max_queue_depth: self.max_depth.load(Ordering::Relaxed),
+            commands_processed: processed,
+            avg_latency_ms,
+            max_latency_ms: self.max_latency_us.load(Ordering::Relaxed) as f64 / 1000.0,
+        }
+    }
+}
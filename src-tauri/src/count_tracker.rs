@@ -0,0 +1,129 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tracing::debug;
+use crate::nostr_types::{NostrResponse, RelayCount};
+
+// How long to wait for COUNT replies before reporting the estimate we have
+pub const COUNT_TIMEOUT: Duration = Duration::from_secs(5);
+
+const COUNT_PREFIX: &str = "cnt-";
+
+struct PendingCount {
+    id: String,
+    estimate: u64,
+    waiting_on: HashSet<String>,
+    relays: Vec<RelayCount>,
+    deadline: Instant,
+}
+
+impl PendingCount {
+    fn into_response(self) -> NostrResponse {
+        NostrResponse::CountDone {
+            id: self.id,
+            estimate: self.estimate,
+            relays: self.relays,
+        }
+    }
+}
+
+/// Collects NIP-45 COUNT replies per request into a best-estimate count.
+/// Counts from different sources overlap, so the estimate is the largest one seen.
+#[derive(Default)]
+pub struct CountTracker {
+    pending: HashMap<String, PendingCount>,
+    // Relays that refused a COUNT, skipped for later requests
+    unsupported: HashSet<String>,
+}
+
+impl CountTracker {
+    /// Relay-level query id for a count request
+    pub fn query_id(id: &str) -> String {
+        format!("{}{}", COUNT_PREFIX, id)
+    }
+
+    pub fn is_count(sub_id: &str) -> bool {
+        sub_id.starts_with(COUNT_PREFIX)
+    }
+
+    pub fn supports(&self, relay_url: &str) -> bool {
+        !self.unsupported.contains(relay_url)
+    }
+
+    pub fn start(&mut self, id: &str, local: u64, relays: Vec<String>) {
+        debug!(id = %id, local = local, relay_count = relays.len(), "Tracking count");
+        self.pending.insert(Self::query_id(id), PendingCount {
+            id: id.to_string(),
+            estimate: local,
+            waiting_on: relays.into_iter().collect(),
+            relays: Vec::new(),
+            deadline: Instant::now() + COUNT_TIMEOUT,
+        });
+    }
+
+    /// Record a relay's COUNT reply. Returns the per-relay update, plus the
+    /// final result once every relay has answered.
+    pub fn relay_count(&mut self, query_id: &str, relay_url: &str, count: u64, approximate: bool) -> Vec<NostrResponse> {
+        let Some(pending) = self.pending.get_mut(query_id) else {
+            return Vec::new();
+        };
+        if !pending.waiting_on.remove(relay_url) {
+            return Vec::new();
+        }
+        pending.estimate = pending.estimate.max(count);
+        pending.relays.push(RelayCount {
+            relay: relay_url.to_string(),
+            count,
+            approximate,
+        });
+
+        let mut responses = vec![NostrResponse::Count {
+            id: pending.id.clone(),
+            relay: Some(relay_url.to_string()),
+            count,
+            approximate,
+            estimate: pending.estimate,
+        }];
+        responses.extend(self.finish_if_done(query_id));
+        responses
+    }
+
+    /// A relay answered the COUNT with CLOSED. Unless it was only a temporary
+    /// refusal (auth, rate limit), don't ask it again.
+    pub fn relay_closed(&mut self, query_id: &str, relay_url: &str, prefix: Option<&str>) -> Option<NostrResponse> {
+        if !matches!(prefix, Some("auth-required") | Some("rate-limited")) {
+            debug!(relay = %relay_url, "Relay does not support COUNT");
+            self.unsupported.insert(relay_url.to_string());
+        }
+        let pending = self.pending.get_mut(query_id)?;
+        if !pending.waiting_on.remove(relay_url) {
+            return None;
+        }
+        self.finish_if_done(query_id)
+    }
+
+    fn finish_if_done(&mut self, query_id: &str) -> Option<NostrResponse> {
+        if !self.pending.get(query_id)?.waiting_on.is_empty() {
+            return None;
+        }
+        Some(self.pending.remove(query_id)?.into_response())
+    }
+
+    /// Earliest count timeout, if any count is waiting
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.values().map(|p| p.deadline).min()
+    }
+
+    /// Finish counts past their deadline with whatever replies arrived
+    pub fn expire(&mut self, now: Instant) -> Vec<NostrResponse> {
+        let expired: Vec<String> = self.pending
+            .iter()
+            .filter(|(_, pending)| pending.deadline <= now)
+            .map(|(query_id, _)| query_id.clone())
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|query_id| Some(self.pending.remove(&query_id)?.into_response()))
+            .collect()
+    }
+}
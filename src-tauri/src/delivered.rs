@@ -0,0 +1,72 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// Event ids remembered per subscription before the oldest is forgotten
+pub const DELIVERED_PER_SUB: usize = 10_000;
+
+#[derive(Default)]
+struct SubDeliveries {
+    ids: HashSet<[u8; 32]>,
+    // Delivery order, for evicting the oldest id
+    order: VecDeque<[u8; 32]>,
+}
+
+/// Event ids already emitted to each subscription. An event can reach a
+/// subscription from its cache query, a nostrdb poll or a relay; it is emitted
+/// once per subscription id, however many paths or relays deliver it.
+/// Only the most recent DELIVERED_PER_SUB ids are remembered per subscription.
+pub struct DeliveredEvents {
+    by_sub: HashMap<String, SubDeliveries>,
+    capacity: usize,
+}
+
+impl Default for DeliveredEvents {
+    fn default() -> Self {
+        Self::new(DELIVERED_PER_SUB)
+    }
+}
+
+impl DeliveredEvents {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            by_sub: HashMap::new(),
+            capacity,
+        }
+    }
+
+    /// Start a subscription with nothing delivered
+    pub fn start(&mut self, sub_id: &str) {
+        self.by_sub.insert(sub_id.to_string(), SubDeliveries::default());
+    }
+
+    pub fn contains(&self, sub_id: &str, event_id: &[u8; 32]) -> bool {
+        self.by_sub.get(sub_id).is_some_and(|sub| sub.ids.contains(event_id))
+    }
+
+    /// Record a delivery. Returns true the first time a started subscription
+    /// gets the event; false for repeats and for sub ids that were never
+    /// started or already ended (negentropy fetches, late relay events).
+    pub fn first_delivery(&mut self, sub_id: &str, event_id: &[u8; 32]) -> bool {
+        let Some(sub) = self.by_sub.get_mut(sub_id) else {
+            return false;
+        };
+        if !sub.ids.insert(*event_id) {
+            return false;
+        }
+        if sub.order.len() >= self.capacity {
+            if let Some(oldest) = sub.order.pop_front() {
+                sub.ids.remove(&oldest);
+            }
+        }
+        sub.order.push_back(*event_id);
+        true
+    }
+
+    /// Forget subscriptions that are no longer active
+    pub fn retain(&mut self, mut active: impl FnMut(&str) -> bool) {
+        self.by_sub.retain(|sub_id, _| active(sub_id));
+    }
+
+    pub fn remove(&mut self, sub_id: &str) {
+        self.by_sub.remove(sub_id);
+    }
+}
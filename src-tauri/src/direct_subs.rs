@@ -0,0 +1,27 @@
+use std::collections::HashSet;
+
+/// Subscriptions nostrdb can't serve live (NIP-50 searches, relay-only tag
+/// filters). They get no nostrdb subscription; relay hits are emitted directly
+/// and merged with the local results through DeliveredEvents.
+#[derive(Default)]
+pub struct DirectSubscriptions {
+    active: HashSet<String>,
+}
+
+impl DirectSubscriptions {
+    pub fn start(&mut self, sub_id: &str) {
+        self.active.insert(sub_id.to_string());
+    }
+
+    pub fn is_active(&self, sub_id: &str) -> bool {
+        self.active.contains(sub_id)
+    }
+
+    pub fn active_count(&self) -> usize {
+        self.active.len()
+    }
+
+    pub fn remove(&mut self, sub_id: &str) {
+        self.active.remove(sub_id);
+    }
+}
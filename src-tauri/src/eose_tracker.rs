@@ -0,0 +1,104 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+// Default wait for slow relays before EOSE is sent anyway
+pub const DEFAULT_EOSE_TIMEOUT: Duration = Duration::from_millis(4000);
+
+struct PendingEose {
+    waiting_on: HashSet<String>,
+    deadline: Instant,
+}
+
+/// Coalesces per-relay EOSE messages into a single EOSE per subscription
+#[derive(Default)]
+pub struct EoseTracker {
+    pending: HashMap<String, PendingEose>,
+    close_on_eose: HashSet<String>,
+}
+
+impl EoseTracker {
+    /// Start waiting for EOSE from the given relays.
+    /// Returns false if there is nothing to wait for (EOSE should be sent now).
+    pub fn start(&mut self, sub_id: &str, relays: Vec<String>, timeout: Duration, close_on_eose: bool) -> bool {
+        if close_on_eose {
+            self.close_on_eose.insert(sub_id.to_string());
+        }
+        if relays.is_empty() {
+            self.pending.remove(sub_id);
+            return false;
+        }
+        debug!(sub_id = %sub_id, relay_count = relays.len(), "Waiting for EOSE");
+        self.pending.insert(sub_id.to_string(), PendingEose {
+            waiting_on: relays.into_iter().collect(),
+            deadline: Instant::now() + timeout,
+        });
+        true
+    }
+
+    /// Record EOSE from a relay. Returns true when the subscription is complete.
+    pub fn relay_eose(&mut self, sub_id: &str, relay_url: &str) -> bool {
+        let done = match self.pending.get_mut(sub_id) {
+            Some(pending) => {
+                pending.waiting_on.remove(relay_url);
+                pending.waiting_on.is_empty()
+            }
+            None => false,
+        };
+        if done {
+            self.pending.remove(sub_id);
+        }
+        done
+    }
+
+    /// Stop waiting on a relay that went away. Returns subscriptions that completed.
+    pub fn relay_gone(&mut self, relay_url: &str) -> Vec<String> {
+        let mut completed = Vec::new();
+        self.pending.retain(|sub_id, pending| {
+            if pending.waiting_on.remove(relay_url) && pending.waiting_on.is_empty() {
+                completed.push(sub_id.clone());
+                return false;
+            }
+            true
+        });
+        completed
+    }
+
+    /// Remove subscriptions past their deadline and return their ids
+    pub fn expire(&mut self, now: Instant) -> Vec<String> {
+        let mut expired = Vec::new();
+        self.pending.retain(|sub_id, pending| {
+            if pending.deadline <= now {
+                debug!(sub_id = %sub_id, missing = pending.waiting_on.len(), "EOSE timeout");
+                expired.push(sub_id.clone());
+                return false;
+            }
+            true
+        });
+        expired
+    }
+
+    /// Earliest EOSE timeout, if any subscription is waiting
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.values().map(|p| p.deadline).min()
+    }
+
+    /// Whether the subscription is still waiting for this relay's EOSE
+    pub fn waits_on(&self, sub_id: &str, relay_url: &str) -> bool {
+        self.pending.get(sub_id).is_some_and(|p| p.waiting_on.contains(relay_url))
+    }
+
+    pub fn closes_on_eose(&self, sub_id: &str) -> bool {
+        self.close_on_eose.contains(sub_id)
+    }
+
+    /// Returns true (once) if the subscription should be closed after its EOSE
+    pub fn take_close_on_eose(&mut self, sub_id: &str) -> bool {
+        self.close_on_eose.remove(sub_id)
+    }
+
+    pub fn remove(&mut self, sub_id: &str) {
+        self.pending.remove(sub_id);
+        self.close_on_eose.remove(sub_id);
+    }
+}
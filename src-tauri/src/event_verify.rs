@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+use secp256k1::{schnorr, Message, XOnlyPublicKey, SECP256K1};
+use sha2::{Digest, Sha256};
+
+// Invalid events tolerated from one source within the window before it is blocked
+const MAX_INVALID_PER_WINDOW: u32 = 5;
+const INVALID_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub enum VerifyError {
+    MissingField(&'static str),
+    InvalidHex(&'static str),
+    IdMismatch,
+    InvalidSignature,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::MissingField(field) => write!(f, "missing or malformed field '{}'", field),
+            VerifyError::InvalidHex(field) => write!(f, "invalid hex in '{}'", field),
+            VerifyError::IdMismatch => write!(f, "id does not match event hash"),
+            VerifyError::InvalidSignature => write!(f, "invalid signature"),
+        }
+    }
+}
+
+/// Check the NIP-01 id hash and BIP-340 signature of a signed event
+pub fn verify_event(event: &serde_json::Value) -> Result<(), VerifyError> {
+    let id = event.get("id").and_then(|v| v.as_str()).ok_or(VerifyError::MissingField("id"))?;
+    let pubkey = event.get("pubkey").and_then(|v| v.as_str()).ok_or(VerifyError::MissingField("pubkey"))?;
+    let sig = event.get("sig").and_then(|v| v.as_str()).ok_or(VerifyError::MissingField("sig"))?;
+    let created_at = event.get("created_at").and_then(|v| v.as_u64()).ok_or(VerifyError::MissingField("created_at"))?;
+    let kind = event.get("kind").and_then(|v| v.as_u64()).ok_or(VerifyError::MissingField("kind"))?;
+    let tags = event.get("tags").filter(|v| v.is_array()).ok_or(VerifyError::MissingField("tags"))?;
+    let content = event.get("content").and_then(|v| v.as_str()).ok_or(VerifyError::MissingField("content"))?;
+
+    let mut id_bytes = [0u8; 32];
+    hex::decode_to_slice(id, &mut id_bytes).map_err(|_| VerifyError::InvalidHex("id"))?;
+    let mut pubkey_bytes = [0u8; 32];
+    hex::decode_to_slice(pubkey, &mut pubkey_bytes).map_err(|_| VerifyError::InvalidHex("pubkey"))?;
+    let mut sig_bytes = [0u8; 64];
+    hex::decode_to_slice(sig, &mut sig_bytes).map_err(|_| VerifyError::InvalidHex("sig"))?;
+
+    // NIP-01: id = sha256 of [0, pubkey, created_at, kind, tags, content]
+    let serialized = serde_json::json!([0, pubkey, created_at, kind, tags, content]).to_string();
+    let hash: [u8; 32] = Sha256::digest(serialized.as_bytes()).into();
+    if hash != id_bytes {
+        return Err(VerifyError::IdMismatch);
+    }
+
+    let pubkey = XOnlyPublicKey::from_slice(&pubkey_bytes).map_err(|_| VerifyError::InvalidHex("pubkey"))?;
+    let sig = schnorr::Signature::from_slice(&sig_bytes).map_err(|_| VerifyError::InvalidHex("sig"))?;
+    let msg = Message::from_digest(hash);
+    SECP256K1
+        .verify_schnorr(&sig, &msg, &pubkey)
+        .map_err(|_| VerifyError::InvalidSignature)
+}
+
+/// Counts invalid events per source (e.g. WebRTC peer) for rate limiting
+#[derive(Default)]
+pub struct InvalidEventCounter {
+    sources: HashMap<String, (u32, Instant)>,
+}
+
+impl InvalidEventCounter {
+    /// Record an invalid event and return the count within the current window
+    pub fn record(&mut self, source: &str) -> u32 {
+        let now = Instant::now();
+        let entry = self.sources.entry(source.to_string()).or_insert((0, now));
+        if now.duration_since(entry.1) > INVALID_WINDOW {
+            *entry = (0, now);
+        }
+        entry.0 += 1;
+        entry.0
+    }
+
+    /// True if the source sent too many invalid events recently
+    pub fn is_limited(&self, source: &str) -> bool {
+        match self.sources.get(source) {
+            Some((count, since)) => {
+                *count >= MAX_INVALID_PER_WINDOW && since.elapsed() <= INVALID_WINDOW
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    // Signed with secret key 3 (BIP-340 test vector 0's key)
+    fn signed_event() -> Value {
+        json!({
+            "id": "c50fc8c054b2b42d580574e8295174dbf32a1124fa53fe5003a95b933f9df659",
+            "pubkey": "f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+            "created_at": 1700000000,
+            "kind": 1,
+            "tags": [["t", "nostr"]],
+            "content": "hello \"iris\"",
+            "sig": "07814ad25443a3b84aa5c0cb5f20ef25d70a9ee33a17d919e04779c90753f255cfc9a20ed54cf1e2a915cb8a9343432c4a6ad3061cfe356878fd2ef994ca0bea"
+        })
+    }
+
+    #[test]
+    fn accepts_valid_event() {
+        assert!(verify_event(&signed_event()).is_ok());
+    }
+
+    #[test]
+    fn rejects_tampered_content() {
+        let mut event = signed_event();
+        event["content"] = json!("hello iris");
+        assert!(matches!(verify_event(&event), Err(VerifyError::IdMismatch)));
+    }
+
+    #[test]
+    fn rejects_bad_signature() {
+        let mut event = signed_event();
+        let mut sig = event["sig"].as_str().unwrap().to_string();
+        sig.replace_range(127.., "b");
+        event["sig"] = json!(sig);
+        assert!(matches!(verify_event(&event), Err(VerifyError::InvalidSignature)));
+    }
+}
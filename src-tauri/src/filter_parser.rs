@@ -1,113 +1,252 @@
-use nostrdb::Filter;
+use std::fmt;
+use nostrdb::{Filter, FilterField};
+use serde_json::{Map, Value};
+
+#[derive(Debug)]
+pub enum FilterError {
+    NotAnObject,
+    NotAnArray(String),
+    EmptyList(String),
+    InvalidHex { field: String, index: usize },
+    InvalidNumber { field: String, index: Option<usize> },
+    NotAString { field: String, index: Option<usize> },
+    EmptyTagName,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::NotAnObject => write!(f, "filter is not an object"),
+            FilterError::NotAnArray(field) => write!(f, "'{}' is not an array", field),
+            FilterError::EmptyList(field) => write!(f, "'{}' is empty and would match nothing", field),
+            FilterError::InvalidHex { field, index } => {
+                write!(f, "'{}'[{}] is not a 32-byte hex string", field, index)
+            }
+            FilterError::InvalidNumber { field, index: Some(index) } => {
+                write!(f, "'{}'[{}] is not a non-negative integer", field, index)
+            }
+            FilterError::InvalidNumber { field, index: None } => {
+                write!(f, "'{}' is not a non-negative integer", field)
+            }
+            FilterError::NotAString { field, index: Some(index) } => {
+                write!(f, "'{}'[{}] is not a string", field, index)
+            }
+            FilterError::NotAString { field, index: None } => write!(f, "'{}' is not a string", field),
+            FilterError::EmptyTagName => write!(f, "'#' tag filter has no tag name"),
+        }
+    }
+}
+
+// Tag filters come in two kinds:
+// - `#` + one ASCII letter (#e, #p, #t, #a, ...) is indexed by nostrdb and by
+//   relays, so it is part of the nostrdb Filter
+// - any other key (#relay, #emoji, ...) can't be expressed in a nostrdb Filter.
+//   It is validated, but a filter containing one is relay-only: it is not queried
+//   or subscribed locally, and is sent to relays as the original JSON.
+
+/// Normalize tag values before the filter is stored or sent anywhere:
+/// hashtags (#t) are matched lowercase, as NIP-24 asks clients to publish them
+pub fn normalize_filter(json: &mut Value) {
+    if let Some(hashtags) = json.get_mut("#t").and_then(|v| v.as_array_mut()) {
+        for tag in hashtags.iter_mut() {
+            if let Some(lower) = tag.as_str().map(str::to_lowercase) {
+                *tag = Value::String(lower);
+            }
+        }
+    }
+}
+
+/// True if the filter has a tag key nostrdb can't index, so only relays can evaluate it
+pub fn has_relay_only_tags(json: &Value) -> bool {
+    json.as_object()
+        .is_some_and(|obj| obj.keys().any(|key| key.starts_with('#') && single_letter_tag(key).is_none()))
+}
+
+fn single_letter_tag(key: &str) -> Option<char> {
+    let mut chars = key.strip_prefix('#')?.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => Some(c),
+        _ => None,
+    }
+}
+
+/// The filter's own `limit`, if it has one
+pub fn filter_limit(filter: &Filter) -> Option<u64> {
+    filter.into_iter().find_map(|field| match field {
+        FilterField::Limit(limit) => Some(limit),
+        _ => None,
+    })
+}
 
 // Helper to convert Vec<T> to Vec<&T> without extra indirection
 fn to_refs<T>(v: &[T]) -> Vec<&T> {
     v.iter().collect()
 }
 
+/// Parse a list of NDK-style JSON filters, naming the failing filter on error
+pub fn parse_filters(filters: &[Value]) -> Result<Vec<Filter>, String> {
+    filters
+        .iter()
+        .enumerate()
+        .map(|(i, f)| parse_filter(f).map_err(|e| format!("filter {}: {}", i, e)))
+        .collect()
+}
+
 /// Parse NDK-style JSON filter to nostrdb::Filter
-pub fn parse_filter(json: &serde_json::Value) -> Option<Filter> {
-    let obj = json.as_object()?;
+pub fn parse_filter(json: &Value) -> Result<Filter, FilterError> {
+    let obj = json.as_object().ok_or(FilterError::NotAnObject)?;
     let mut builder = Filter::new();
 
     // Parse authors (hex strings -> byte arrays)
-    if let Some(authors) = obj.get("authors").and_then(|v| v.as_array()) {
-        let author_bytes: Vec<[u8; 32]> = authors
-            .iter()
-            .filter_map(|v| v.as_str())
-            .filter_map(|s| {
-                hex::decode(s).ok()?.try_into().ok()
-            })
-            .collect();
-        if !author_bytes.is_empty() {
-            builder = builder.authors(to_refs(&author_bytes));
-        }
+    if let Some(author_bytes) = hex_list(obj, "authors")? {
+        builder = builder.authors(to_refs(&author_bytes));
     }
 
     // Parse kinds
-    if let Some(kinds) = obj.get("kinds").and_then(|v| v.as_array()) {
-        let kind_nums: Vec<u64> = kinds
+    if let Some(kinds) = list(obj, "kinds")? {
+        let kind_nums = kinds
             .iter()
-            .filter_map(|v| v.as_u64())
-            .collect();
-        if !kind_nums.is_empty() {
-            builder = builder.kinds(kind_nums);
-        }
+            .enumerate()
+            .map(|(index, v)| {
+                v.as_u64().ok_or_else(|| FilterError::InvalidNumber { field: "kinds".to_string(), index: Some(index) })
+            })
+            .collect::<Result<Vec<u64>, _>>()?;
+        builder = builder.kinds(kind_nums);
     }
 
     // Parse IDs (hex strings -> byte arrays)
-    if let Some(ids) = obj.get("ids").and_then(|v| v.as_array()) {
-        let id_bytes: Vec<[u8; 32]> = ids
-            .iter()
-            .filter_map(|v| v.as_str())
-            .filter_map(|s| {
-                hex::decode(s).ok()?.try_into().ok()
-            })
-            .collect();
-        if !id_bytes.is_empty() {
-            builder = builder.ids(to_refs(&id_bytes));
-        }
+    if let Some(id_bytes) = hex_list(obj, "ids")? {
+        builder = builder.ids(to_refs(&id_bytes));
     }
 
-    // Parse #e tags (hex strings -> byte arrays)
-    if let Some(e_tags) = obj.get("#e").and_then(|v| v.as_array()) {
-        let e_bytes: Vec<[u8; 32]> = e_tags
-            .iter()
-            .filter_map(|v| v.as_str())
-            .filter_map(|s| {
-                hex::decode(s).ok()?.try_into().ok()
-            })
-            .collect();
-        if !e_bytes.is_empty() {
-            builder = builder.events(to_refs(&e_bytes));
+    // Parse tag filters. #e/#p with 32-byte hex values use nostrdb's id index,
+    // #e/#p without any (and every other single-letter tag) match as strings.
+    // A list mixing the two is rejected rather than matched all as strings.
+    for (key, value) in obj.iter() {
+        if !key.starts_with('#') {
+            continue;
         }
-    }
-
-    // Parse #p tags (hex strings -> byte arrays)
-    if let Some(p_tags) = obj.get("#p").and_then(|v| v.as_array()) {
-        let p_bytes: Vec<[u8; 32]> = p_tags
-            .iter()
-            .filter_map(|v| v.as_str())
-            .filter_map(|s| {
-                hex::decode(s).ok()?.try_into().ok()
-            })
-            .collect();
-        if !p_bytes.is_empty() {
-            builder = builder.pubkeys(to_refs(&p_bytes));
+        if key.len() == 1 {
+            return Err(FilterError::EmptyTagName);
         }
-    }
+        let tag_strs = tag_values(key, value)?;
+        let Some(tag_char) = single_letter_tag(key) else {
+            // Relay-only tag, see has_relay_only_tags
+            continue;
+        };
 
-    // Parse all generic #<char> tags dynamically
-    for (key, value) in obj.iter() {
-        if key.starts_with('#') && key.len() == 2 {
-            if let Some(tag_char) = key.chars().nth(1) {
-                if let Some(tag_values) = value.as_array() {
-                    let tag_strs: Vec<&str> = tag_values
-                        .iter()
-                        .filter_map(|v| v.as_str())
-                        .collect();
-                    if !tag_strs.is_empty() {
-                        builder = builder.tags(tag_strs, tag_char);
-                    }
-                }
-            }
-        }
+        let ids = match tag_char {
+            'e' | 'p' => hex_ids(key, &tag_strs)?,
+            _ => None,
+        };
+        builder = match (tag_char, ids) {
+            ('e', Some(ids)) => builder.events(to_refs(&ids)),
+            ('p', Some(ids)) => builder.pubkeys(to_refs(&ids)),
+            _ => builder.tags(tag_strs, tag_char),
+        };
     }
 
     // Parse since
-    if let Some(since) = obj.get("since").and_then(|v| v.as_u64()) {
+    if let Some(since) = number(obj, "since")? {
         builder = builder.since(since);
     }
 
     // Parse until
-    if let Some(until) = obj.get("until").and_then(|v| v.as_u64()) {
+    if let Some(until) = number(obj, "until")? {
         builder = builder.until(until);
     }
 
     // Parse limit
-    if let Some(limit) = obj.get("limit").and_then(|v| v.as_u64()) {
+    if let Some(limit) = number(obj, "limit")? {
         builder = builder.limit(limit);
     }
 
-    Some(builder.build())
+    // Parse NIP-50 search (nostrdb full-text search)
+    if let Some(search) = obj.get("search") {
+        let search = search
+            .as_str()
+            .ok_or_else(|| FilterError::NotAString { field: "search".to_string(), index: None })?;
+        if !search.is_empty() {
+            builder = builder.search(search);
+        }
+    }
+
+    Ok(builder.build())
+}
+
+/// A non-empty array field, if present
+fn list<'a>(obj: &'a Map<String, Value>, field: &str) -> Result<Option<&'a Vec<Value>>, FilterError> {
+    let Some(value) = obj.get(field) else {
+        return Ok(None);
+    };
+    let values = value.as_array().ok_or_else(|| FilterError::NotAnArray(field.to_string()))?;
+    if values.is_empty() {
+        return Err(FilterError::EmptyList(field.to_string()));
+    }
+    Ok(Some(values))
+}
+
+/// Tag filter values: a non-empty array of strings
+fn tag_values<'a>(key: &str, value: &'a Value) -> Result<Vec<&'a str>, FilterError> {
+    let values = value.as_array().ok_or_else(|| FilterError::NotAnArray(key.to_string()))?;
+    if values.is_empty() {
+        return Err(FilterError::EmptyList(key.to_string()));
+    }
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, v)| {
+            v.as_str().ok_or_else(|| FilterError::NotAString { field: key.to_string(), index: Some(index) })
+        })
+        .collect()
+}
+
+/// Decode #e/#p values as event ids / pubkeys. None if no value is 32-byte
+/// hex; an error if only some are.
+fn hex_ids(key: &str, values: &[&str]) -> Result<Option<Vec<[u8; 32]>>, FilterError> {
+    let decoded: Vec<Option<[u8; 32]>> = values
+        .iter()
+        .map(|s| {
+            let mut bytes = [0u8; 32];
+            hex::decode_to_slice(s, &mut bytes).ok().map(|_| bytes)
+        })
+        .collect();
+    if decoded.iter().all(Option::is_none) {
+        return Ok(None);
+    }
+    decoded
+        .into_iter()
+        .enumerate()
+        .map(|(index, id)| id.ok_or_else(|| FilterError::InvalidHex { field: key.to_string(), index }))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+/// A list of 32-byte hex strings (ids, pubkeys), if present
+fn hex_list(obj: &Map<String, Value>, field: &str) -> Result<Option<Vec<[u8; 32]>>, FilterError> {
+    let Some(values) = list(obj, field)? else {
+        return Ok(None);
+    };
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, v)| {
+            let mut bytes = [0u8; 32];
+            v.as_str()
+                .filter(|s| hex::decode_to_slice(s, &mut bytes).is_ok())
+                .map(|_| bytes)
+                .ok_or_else(|| FilterError::InvalidHex { field: field.to_string(), index })
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+fn number(obj: &Map<String, Value>, field: &str) -> Result<Option<u64>, FilterError> {
+    match obj.get(field) {
+        None => Ok(None),
+        Some(v) => v
+            .as_u64()
+            .map(Some)
+            .ok_or_else(|| FilterError::InvalidNumber { field: field.to_string(), index: None }),
+    }
 }
@@ -0,0 +1,88 @@
+use std::collections::{HashMap, HashSet};
+use tracing::debug;
+
+/// A relay's EOSE, held until nostrdb has stored the events sent before it
+struct HeldEose {
+    relay: String,
+    sub_id: String,
+    // Every event was stored at the last check; released after the next full poll
+    stored: bool,
+}
+
+/// Orders a relay's EOSE after the events it follows. nostrdb ingests events
+/// on its own threads and subscriptions only see them in a later poll, so an
+/// EOSE handled on arrival could finish a subscription (and tear down a
+/// closeOnEose one) before those events reach it.
+#[derive(Default)]
+pub struct IngestTracker {
+    // (relay, relay-level sub id) -> event ids handed to nostrdb before that relay's EOSE
+    ingesting: HashMap<(String, String), HashSet<[u8; 32]>>,
+    held: Vec<HeldEose>,
+}
+
+impl IngestTracker {
+    /// An event for a subscription still waiting on this relay's EOSE went to nostrdb
+    pub fn ingesting(&mut self, relay: &str, sub_id: &str, event_id: &[u8; 32]) {
+        self.ingesting
+            .entry((relay.to_string(), sub_id.to_string()))
+            .or_default()
+            .insert(*event_id);
+    }
+
+    /// A relay sent EOSE. Returns true if it has to wait for events still being ingested.
+    pub fn hold_eose(&mut self, relay: &str, sub_id: &str) -> bool {
+        let key = (relay.to_string(), sub_id.to_string());
+        let pending = matches!(self.ingesting.get(&key), Some(ids) if !ids.is_empty());
+        if !pending {
+            self.ingesting.remove(&key);
+            return false;
+        }
+        debug!(relay = %relay, sub_id = %sub_id, "Holding EOSE until nostrdb stores earlier events");
+        self.held.push(HeldEose {
+            relay: key.0,
+            sub_id: key.1,
+            stored: false,
+        });
+        true
+    }
+
+    pub fn has_held(&self) -> bool {
+        !self.held.is_empty()
+    }
+
+    /// Check held EOSEs after a poll. `drained` means the poll emptied every
+    /// nostrdb subscription queue. Returns the (relay, sub id) EOSEs whose
+    /// events were all stored before the previous check and have been polled
+    /// since. Entries `waiting` no longer wants (finished, timed out, closed)
+    /// are dropped.
+    pub fn release(
+        &mut self,
+        drained: bool,
+        mut is_stored: impl FnMut(&[u8; 32]) -> bool,
+        mut waiting: impl FnMut(&str, &str) -> bool,
+    ) -> Vec<(String, String)> {
+        self.ingesting.retain(|(relay, sub_id), _| waiting(relay, sub_id));
+
+        let mut released = Vec::new();
+        for mut held in std::mem::take(&mut self.held) {
+            if !waiting(&held.relay, &held.sub_id) {
+                continue;
+            }
+            let key = (held.relay.clone(), held.sub_id.clone());
+            if held.stored && drained {
+                self.ingesting.remove(&key);
+                released.push(key);
+                continue;
+            }
+            held.stored = match self.ingesting.get_mut(&key) {
+                Some(ids) => {
+                    ids.retain(|id| !is_stored(id));
+                    ids.is_empty()
+                }
+                None => true,
+            };
+            self.held.push(held);
+        }
+        released
+    }
+}
@@ -1,25 +1,67 @@
+mod checkpoint;
+mod command_queue;
+mod delivered;
+mod direct_subs;
+mod count_tracker;
+mod eose_tracker;
+mod event_verify;
 mod filter_parser;
+mod ingest_tracker;
+mod ndb_stats;
 mod nostr_types;
 mod nostr_thread;
+mod outbox;
+mod publish_tracker;
+mod relay_auth;
 mod relay_handlers;
+mod relay_message;
+mod search;
+mod seen_events;
+mod subscription_grouper;
 mod subscription_handlers;
+mod wakeup;
 
 #[cfg(mobile)]
 use tauri::Listener;
 use tauri::Manager;
+use tauri::ipc::Channel;
+use std::sync::Arc;
 use std::sync::mpsc::{channel, Sender};
 use tracing::{info, error};
 use tracing_subscriber::EnvFilter;
-use nostr_types::NostrRequest;
+use nostr_types::{CachedEvents, NostrRequest};
 use nostr_thread::nostr_thread;
+use wakeup::Wakeup;
+use command_queue::{CommandMetrics, QueuedCommand};
+use checkpoint::SharedCheckpoint;
 
 struct AppState {
-    nostr_tx: Sender<NostrRequest>,
+    nostr_tx: Sender<QueuedCommand>,
+    wakeup: Wakeup,
+    metrics: Arc<CommandMetrics>,
 }
 
 #[tauri::command]
 async fn nostr_message(msg: NostrRequest, state: tauri::State<'_, AppState>) -> Result<(), String> {
-    state.nostr_tx.send(msg).map_err(|e| e.to_string())
+    // Count before sending so the nostr thread never dequeues an uncounted command
+    state.metrics.queued();
+    state.nostr_tx.send(QueuedCommand::new(msg)).map_err(|e| e.to_string())?;
+    state.wakeup.wake();
+    Ok(())
+}
+
+/// Subscribe with a channel that receives cached events in batches instead of
+/// one `nostr_event` per note. Relay events and EOSE still arrive as events.
+#[tauri::command]
+async fn nostr_subscribe(
+    msg: NostrRequest,
+    on_cached: Channel<CachedEvents>,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    state.metrics.queued();
+    state.nostr_tx.send(QueuedCommand::with_channel(msg, on_cached)).map_err(|e| e.to_string())?;
+    state.wakeup.wake();
+    Ok(())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -37,7 +79,7 @@ pub fn run() {
 
     tauri::Builder::default()
         .plugin(tauri_plugin_os::init())
-        .invoke_handler(tauri::generate_handler![nostr_message])
+        .invoke_handler(tauri::generate_handler![nostr_message, nostr_subscribe])
         .setup(|app| {
             let data_dir = app.path().app_data_dir().expect("failed to get app data dir");
             std::fs::create_dir_all(&data_dir).expect("failed to create data dir");
@@ -45,18 +87,36 @@ pub fn run() {
             std::fs::create_dir_all(&db_path).expect("failed to create db dir");
 
             let (tx, rx) = channel();
+            let wakeup = Wakeup::default();
+            let thread_wakeup = wakeup.clone();
+            let metrics = Arc::new(CommandMetrics::default());
+            let thread_metrics = metrics.clone();
+            // Relays and subscriptions, kept across nostr thread restarts
+            let checkpoint = SharedCheckpoint::default();
 
             let db_path_str = db_path.to_str().unwrap().to_string();
+            // Outbox lives next to the nostrdb directory so it survives restarts
+            let outbox_path = data_dir.join("outbox.json");
             let app_handle = app.handle().clone();
 
             // Spawn with panic recovery
             std::thread::Builder::new()
                 .name("nostr".into())
                 .spawn(move || {
+                    let mut restarted = false;
                     loop {
                         let handle_clone = app_handle.clone();
                         let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-                            nostr_thread(&rx, &db_path_str, handle_clone);
+                            nostr_thread(
+                                &rx,
+                                &thread_wakeup,
+                                &thread_metrics,
+                                &checkpoint,
+                                restarted,
+                                &db_path_str,
+                                &outbox_path,
+                                handle_clone,
+                            );
                         }));
 
                         match result {
@@ -67,14 +127,15 @@ pub fn run() {
                             Err(e) => {
                                 error!(panic = ?e, "Nostr thread panicked, restarting");
                                 std::thread::sleep(std::time::Duration::from_secs(1));
-                                // Thread restarts loop
+                                // Thread restarts loop, restoring checkpointed state
+                                restarted = true;
                             }
                         }
                     }
                 })
                 .expect("failed to spawn nostr thread");
 
-            app.manage(AppState { nostr_tx: tx });
+            app.manage(AppState { nostr_tx: tx, wakeup, metrics });
 
             // Logging handled by tracing-subscriber
 
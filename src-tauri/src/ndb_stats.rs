@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::path::Path;
+use nostrdb::{bindings, Ndb};
+use crate::nostr_types::{IndexStats, LocalDataStats};
+
+// Event kinds for nostrdb's ndb_common_kind slots, in enum order
+const COMMON_KINDS: [u32; 15] = [
+    0, 1, 3, 4, 5, 6, 7, 9735, 9734, 23194, 23195, 27235, 30000, 30023, 30315,
+];
+
+/// Collect per-kind counts and per-index sizes via ndb_stat
+pub fn collect_stats(ndb: &Ndb, db_path: &str) -> Result<LocalDataStats, String> {
+    let mut stat: bindings::ndb_stat = unsafe { std::mem::zeroed() };
+    if unsafe { bindings::ndb_stat(ndb.as_ptr(), &mut stat) } == 0 {
+        return Err("ndb_stat failed".to_string());
+    }
+
+    let mut events_by_kind = HashMap::new();
+    for (i, counts) in stat.common_kinds.iter().enumerate() {
+        if let Some(kind) = COMMON_KINDS.get(i) {
+            if counts.count > 0 {
+                events_by_kind.insert(*kind, counts.count);
+            }
+        }
+    }
+
+    let index_sizes: Vec<IndexStats> = stat.dbs
+        .iter()
+        .enumerate()
+        .map(|(i, counts)| IndexStats {
+            name: db_name(i),
+            count: counts.count,
+            key_size: counts.key_size,
+            value_size: counts.value_size,
+        })
+        .collect();
+
+    let database_size_bytes = database_file_size(db_path);
+
+    Ok(LocalDataStats {
+        total_events: stat.dbs[bindings::ndb_dbs_NDB_DB_NOTE as usize].count,
+        events_by_kind,
+        other_kinds: stat.other_kinds.count,
+        database_size_bytes,
+        index_sizes,
+    })
+}
+
+fn db_name(index: usize) -> String {
+    let name = unsafe { bindings::ndb_db_name(index as bindings::ndb_dbs) };
+    if name.is_null() {
+        return format!("db{}", index);
+    }
+    unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned()
+}
+
+// LMDB keeps all indexes in a single data.mdb file
+fn database_file_size(db_path: &str) -> u64 {
+    std::fs::metadata(Path::new(db_path).join("data.mdb"))
+        .map(|m| m.len())
+        .unwrap_or(0)
+}
@@ -1,30 +1,83 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::mpsc::Receiver;
 use nostrdb::{Ndb, Config, Subscription};
-use enostr::{RelayPool, ewebsock};
+use enostr::{RelayPool, ClientMessage, ewebsock};
 use tauri::Emitter;
+use tauri::ipc::Channel;
 use tracing::{debug, info, warn, error};
-use crate::nostr_types::{NostrRequest, NostrResponse, RelayStatusInfo};
+use crate::nostr_types::{CachedEvents, NostrRequest, NostrResponse, RelayStatusInfo};
 use crate::relay_handlers;
 use crate::subscription_handlers;
+use crate::eose_tracker::EoseTracker;
+use crate::subscription_grouper::SubscriptionGrouper;
+use crate::publish_tracker::PublishTracker;
+use crate::count_tracker::CountTracker;
+use crate::search::SearchState;
+use crate::direct_subs::DirectSubscriptions;
+use crate::delivered::DeliveredEvents;
+use crate::outbox::Outbox;
+use crate::event_verify::InvalidEventCounter;
+use crate::wakeup::Wakeup;
+use crate::command_queue::{CommandMetrics, QueuedCommand};
+use crate::checkpoint::SharedCheckpoint;
+use crate::relay_auth::{AuthRetry, ClosedSub, RelayAuth};
+use crate::filter_parser::normalize_filter;
+use crate::relay_message::{self, RelayMessage};
+use crate::seen_events::SeenEvents;
+use crate::ingest_tracker::IngestTracker;
+
+// Upper bound on idle blocking, for outbox retries and relay reconnects
+const MAX_IDLE_WAIT: std::time::Duration = std::time::Duration::from_secs(1);
+// Max commands / relay messages handled per loop pass before switching to the other
+const COMMAND_BATCH: usize = 64;
+const RELAY_BATCH: usize = 256;
+// How often to check on nostrdb ingestion while an EOSE is held for it
+const INGEST_POLL: std::time::Duration = std::time::Duration::from_millis(10);
 
 thread_local! {
     static NDB: RefCell<Option<Ndb>> = RefCell::new(None);
     static POOL: RefCell<Option<RelayPool>> = RefCell::new(None);
     static SUBSCRIPTIONS: RefCell<HashMap<String, Subscription>> = RefCell::new(HashMap::new());
     static SUB_ID_MAP: RefCell<HashMap<u64, String>> = RefCell::new(HashMap::new());
+    static EOSE_TRACKER: RefCell<EoseTracker> = RefCell::new(EoseTracker::default());
+    static SUB_GROUPER: RefCell<SubscriptionGrouper> = RefCell::new(SubscriptionGrouper::default());
+    static PUBLISH_TRACKER: RefCell<PublishTracker> = RefCell::new(PublishTracker::default());
+    static OUTBOX: RefCell<Option<Outbox>> = RefCell::new(None);
+    static INVALID_EVENTS: RefCell<InvalidEventCounter> = RefCell::new(InvalidEventCounter::default());
+    static RELAY_AUTH: RefCell<RelayAuth> = RefCell::new(RelayAuth::default());
+    static COUNT_TRACKER: RefCell<CountTracker> = RefCell::new(CountTracker::default());
+    static SEARCH: RefCell<SearchState> = RefCell::new(SearchState::default());
+    static DIRECT_SUBS: RefCell<DirectSubscriptions> = RefCell::new(DirectSubscriptions::default());
+    static SEEN_EVENTS: RefCell<SeenEvents> = RefCell::new(SeenEvents::default());
+    static DELIVERED: RefCell<DeliveredEvents> = RefCell::new(DeliveredEvents::default());
+    static INGEST: RefCell<IngestTracker> = RefCell::new(IngestTracker::default());
 }
 
-pub fn nostr_thread(rx: &Receiver<NostrRequest>, db_path: &str, app_handle: tauri::AppHandle) {
+pub fn nostr_thread(
+    rx: &Receiver<QueuedCommand>,
+    wakeup: &Wakeup,
+    metrics: &CommandMetrics,
+    checkpoint: &SharedCheckpoint,
+    restarted: bool,
+    db_path: &str,
+    outbox_path: &Path,
+    app_handle: tauri::AppHandle,
+) {
     info!(target: "iris", "Initializing nostrdb and relay pool");
-    let config = Config::new();
+    // Thread-locals outlive a caught panic - close the previous Ndb/pool and
+    // drop state tied to them before reopening
+    reset_subscription_state();
+
+    // Wake the loop when a nostrdb subscription has new notes
+    let sub_wakeup = wakeup.clone();
+    let config = Config::new().set_sub_callback(move |_sub_id| sub_wakeup.wake());
     let ndb = Ndb::new(&db_path, &config).expect("failed to initialize nostrdb");
     let mut pool = RelayPool::new();
 
     // Add multicast relay for local network discovery (WebRTC signaling)
-    let wakeup = || {}; // No-op wakeup
-    match enostr::PoolRelay::multicast(wakeup) {
+    match enostr::PoolRelay::multicast(wakeup.callback()) {
         Ok(multicast_relay) => {
             pool.relays.push(multicast_relay);
             info!("Multicast relay enabled for local network discovery");
@@ -36,6 +89,11 @@ pub fn nostr_thread(rx: &Receiver<NostrRequest>, db_path: &str, app_handle: taur
 
     NDB.with(|n| *n.borrow_mut() = Some(ndb));
     POOL.with(|p| *p.borrow_mut() = Some(pool));
+    OUTBOX.with(|o| *o.borrow_mut() = Some(Outbox::load(outbox_path)));
+
+    if restarted {
+        restore_checkpoint(checkpoint, wakeup, &app_handle);
+    }
 
     loop {
         let mut had_activity = false;
@@ -43,7 +101,10 @@ pub fn nostr_thread(rx: &Receiver<NostrRequest>, db_path: &str, app_handle: taur
         // Process relay events
         POOL.with(|p| {
             if let Some(pool) = p.borrow_mut().as_mut() {
-                while let Some(pool_event) = pool.try_recv() {
+                for _ in 0..RELAY_BATCH {
+                    let Some(pool_event) = pool.try_recv() else {
+                        break;
+                    };
                     had_activity = true;
                     let relay_url = pool_event.relay.clone();
                     let event = pool_event.event;
@@ -58,12 +119,17 @@ pub fn nostr_thread(rx: &Receiver<NostrRequest>, db_path: &str, app_handle: taur
                                 NDB.with(|n| {
                                     if let Some(ndb) = n.borrow().as_ref() {
                                         if let Ok(txn) = nostrdb::Transaction::new(ndb) {
-                                            let notes = match ndb.query(&txn, &[filter.clone()], 1000) {
+                                            let limit = subscription_handlers::query_limit(std::slice::from_ref(&filter), None);
+                                            let notes = match ndb.query(&txn, &[filter.clone()], limit as i32) {
                                                 Ok(results) => {
                                                     results.iter().map(|r| r.note.clone()).collect::<Vec<_>>()
                                                 }
                                                 Err(_) => vec![],
                                             };
+                                            // A partial local set makes the relay think we lack the rest
+                                            if notes.len() >= limit as usize {
+                                                warn!(sub_id = %sub_id, limit = limit, "Negentropy local set hit the query limit");
+                                            }
                                             debug!("Providing {} local events for negentropy sync", notes.len());
                                             if let Err(e) = pool.add_negentropy_notes(&relay_url, &sub_id, filter, &notes) {
                                                 warn!("Failed to add negentropy notes: {}", e);
@@ -91,8 +157,18 @@ pub fn nostr_thread(rx: &Receiver<NostrRequest>, db_path: &str, app_handle: taur
                                 debug!("Negentropy HaveEvents: we have {} events relay doesn't", event_ids.len());
                                 // We have these, relay doesn't - could upload if bidirectional
                             }
-                            NegentropyEvent::SyncComplete { sub_id, .. } => {
+                            NegentropyEvent::SyncComplete { relay_url, sub_id, .. } => {
                                 debug!("Negentropy sync complete for {}", sub_id);
+                                // Negentropy replaces the REQ, so no EOSE will follow from this relay
+                                NDB.with(|n| {
+                                    if let Some(ndb) = n.borrow_mut().as_mut() {
+                                        with_sub_state(|subs, map, tracker, grouper, direct| {
+                                            subscription_handlers::handle_eose_message(
+                                                &sub_id, &relay_url, ndb, pool, subs, map, tracker, grouper, direct, &app_handle,
+                                            );
+                                        });
+                                    }
+                                });
                             }
                             NegentropyEvent::Error { sub_id, error, .. } => {
                                 warn!("Negentropy error for {}: {}", sub_id, error);
@@ -102,76 +178,177 @@ pub fn nostr_thread(rx: &Receiver<NostrRequest>, db_path: &str, app_handle: taur
 
                     match event {
                         ewebsock::WsEvent::Message(ewebsock::WsMessage::Text(text)) => {
-                            // Fast path: check for duplicate EVENT messages using string ops
-                            let mut already_had = false;
-                            if text.len() > 3 && text.as_bytes()[2] == b'E' && text.as_bytes()[3] == b'V' {
-                                    if let Some(id_pos) = text.find(r#""id":""#) {
-                                        let id_start = id_pos + 6;
-                                        let id_end = id_start + 64;
-                                        if id_end <= text.len() {
-                                            let id_str = &text[id_start..id_end];
-                                            let mut id_bytes = [0u8; 32];
-                                            if hex::decode_to_slice(id_str, &mut id_bytes).is_ok() {
-                                                NDB.with(|n| {
-                                                    if let Some(ndb) = n.borrow().as_ref() {
-                                                        if let Ok(txn) = nostrdb::Transaction::new(ndb) {
-                                                            already_had = ndb.get_notekey_by_id(&txn, &id_bytes).is_ok();
-                                                        }
-                                                    }
-                                                });
-                                            }
-                                        }
-                                    }
-                                }
+                                debug!(relay = %relay_url, len = text.len(), "Received message");
 
-                                // Skip if already had - don't process or forward
-                                if already_had {
+                                // Classify once; strings and the event object borrow from the frame
+                                let Some(message) = RelayMessage::parse(&text) else {
+                                    debug!(relay = %relay_url, msg = %&text[..text.len().min(100)], "Unknown message");
                                     continue;
-                                }
+                                };
 
-                                debug!(relay = %relay_url, len = text.len(), "Received message");
-
-                                // Process through nostrdb (validates signature)
                                 NDB.with(|n| {
                                     if let Some(ndb) = n.borrow_mut().as_mut() {
-                                        // Try to parse as relay message first
-                                        if let Ok(msg) = serde_json::from_str::<serde_json::Value>(&text) {
-                                            if let Some(arr) = msg.as_array() {
-                                                match arr.get(0).and_then(|v| v.as_str()) {
-                                                    Some("EVENT") if arr.len() >= 3 => {
-                                                        // Validate with nostrdb
-                                                        match ndb.process_event(&text) {
-                                                            Ok(_) => {
-                                                                if let (Some(sub_id), Some(event)) = (arr[1].as_str(), arr.get(2)) {
-                                                                    let _ = app_handle.emit("nostr_event", NostrResponse::Event {
-                                                                        sub_id: sub_id.to_string(),
-                                                                        event: event.clone(),
-                                                                        relay: Some(relay_url.clone()),
-                                                                    });
-                                                                }
-                                                            }
-                                                            Err(e) => {
-                                                                warn!(error = ?e, "Event rejected");
-                                                            }
-                                                        }
+                                        match message {
+                                            RelayMessage::Event { sub_id, event } => {
+                                                let Some(event_id) = relay_message::event_id(event) else {
+                                                    debug!(relay = %relay_url, sub_id = %sub_id, "Event without a valid id");
+                                                    return;
+                                                };
+                                                // Duplicate check: the seen-id cache first, then nostrdb. Only ids
+                                                // nostrdb has stored count, so a forged copy can't shadow the real event.
+                                                let mut stored_sig = SEEN_EVENTS.with(|s| s.borrow().stored_sig(&event_id));
+                                                if stored_sig.is_none() {
+                                                    if let Ok(txn) = nostrdb::Transaction::new(ndb) {
+                                                        stored_sig = ndb.get_note_by_id(&txn, &event_id).ok().map(|note| *note.sig());
                                                     }
-                                                    Some("NOTICE") if arr.len() >= 2 => {
-                                                        if let Some(notice) = arr[1].as_str() {
-                                                            warn!(relay = %relay_url, notice = %notice, "Relay notice");
-                                                        }
+                                                    if let Some(sig) = stored_sig {
+                                                        SEEN_EVENTS.with(|s| s.borrow_mut().mark_stored(&event_id, sig));
                                                     }
-                                                    Some("EOSE") if arr.len() >= 2 => {
-                                                        if let Some(sub_id) = arr[1].as_str() {
-                                                            debug!(relay = %relay_url, sub_id = %sub_id, "End of stored events (not forwarding to frontend)");
-                                                            // Don't forward EOSEs - they flood the IPC channel
-                                                        }
+                                                }
+                                                // Already stored: skip nostrdb processing, which wouldn't report it to
+                                                // subscriptions again, but still give it to those that don't have it.
+                                                // The relay is "seen on" only if its copy carries the stored signature.
+                                                if let Some(sig) = stored_sig {
+                                                    if relay_message::event_sig(event) != Some(sig) {
+                                                        debug!(relay = %relay_url, sub_id = %sub_id, "Stored event copy with a different signature");
+                                                        return;
                                                     }
-                                                    Some("OK") => {
-                                                        debug!(relay = %relay_url, "Event accepted");
+                                                    SEEN_EVENTS.with(|s| s.borrow_mut().record(&event_id, &relay_url));
+                                                    emit_stored(ndb, &sub_id, &event_id, &relay_url, &app_handle);
+                                                    return;
+                                                }
+
+                                                // Validate with nostrdb, which parses the raw frame itself. Subscriptions
+                                                // backed by a nostrdb subscription receive the note when it is polled
+                                                // below; only forward directly for the rest (ID lookups, fetches).
+                                                if let Err(e) = ndb.process_event(&text) {
+                                                    warn!(error = ?e, "Event rejected");
+                                                    return;
+                                                }
+                                                SEEN_EVENTS.with(|s| s.borrow_mut().record(&event_id, &relay_url));
+                                                // Group REQs are demultiplexed by the members' nostrdb subscriptions
+                                                let has_ndb_sub = SubscriptionGrouper::is_group(&sub_id)
+                                                    || SUBSCRIPTIONS.with(|subs| subs.borrow().contains_key(sub_id.as_ref()));
+                                                if has_ndb_sub {
+                                                    // This relay's EOSE waits until nostrdb stores it and it is polled
+                                                    if waits_for_eose(&sub_id, &relay_url) {
+                                                        INGEST.with(|i| i.borrow_mut().ingesting(&relay_url, &sub_id, &event_id));
                                                     }
-                                                    _ => {
-                                                        debug!(relay = %relay_url, msg = %&text[..text.len().min(100)], "Unknown message");
+                                                    return;
+                                                }
+                                                // Direct subscriptions may already have this from the local query or another
+                                                // relay; ids that aren't subscriptions (negentropy fetches, late events after
+                                                // unsubscribe) are only stored
+                                                if !DELIVERED.with(|d| d.borrow_mut().first_delivery(&sub_id, &event_id)) {
+                                                    return;
+                                                }
+                                                let _ = app_handle.emit("nostr_event", NostrResponse::Event {
+                                                    sub_id: sub_id.into_owned(),
+                                                    event: event.to_owned(),
+                                                    relay: Some(relay_url.clone()),
+                                                });
+                                            }
+                                            RelayMessage::Notice { message } => {
+                                                warn!(relay = %relay_url, notice = %message, "Relay notice");
+                                                let _ = app_handle.emit("nostr_event", NostrResponse::Notice {
+                                                    relay: relay_url.clone(),
+                                                    notice: message.into_owned(),
+                                                });
+                                            }
+                                            RelayMessage::Eose { sub_id } => {
+                                                debug!(relay = %relay_url, sub_id = %sub_id, "End of stored events");
+                                                // Handled after the poll that delivers the events before it
+                                                if INGEST.with(|i| i.borrow_mut().hold_eose(&relay_url, &sub_id)) {
+                                                    return;
+                                                }
+                                                // Coalesce across relays - one EOSE per subscription
+                                                with_sub_state(|subs, map, tracker, grouper, direct| {
+                                                    subscription_handlers::handle_eose_message(
+                                                        &sub_id, &relay_url, ndb, pool, subs, map, tracker, grouper, direct, &app_handle,
+                                                    );
+                                                });
+                                            }
+                                            RelayMessage::Closed { sub_id, reason } => {
+                                                if CountTracker::is_count(&sub_id) {
+                                                    let prefix = subscription_handlers::message_prefix(&reason);
+                                                    debug!(relay = %relay_url, query_id = %sub_id, reason = %reason, "Relay closed COUNT");
+                                                    let done = COUNT_TRACKER.with(|t| t.borrow_mut().relay_closed(&sub_id, &relay_url, prefix));
+                                                    if let Some(done) = done {
+                                                        let _ = app_handle.emit("nostr_event", done);
+                                                    }
+                                                    return;
+                                                }
+                                                let auth_required = subscription_handlers::message_prefix(&reason) == Some("auth-required");
+                                                let hold_eose = auth_required && RELAY_AUTH.with(|a| a.borrow().holds_eose(&relay_url));
+                                                if auth_required {
+                                                    // Taken before a closeOnEose subscription can finish and leave the checkpoint
+                                                    let closed = closed_subs(&sub_id, checkpoint);
+                                                    let request = RELAY_AUTH.with(|a| a.borrow_mut().sub_needs_auth(&relay_url, closed));
+                                                    if let Some(request) = request {
+                                                        let _ = app_handle.emit("nostr_event", request);
+                                                    }
+                                                }
+                                                with_sub_state(|subs, map, tracker, grouper, direct| {
+                                                    subscription_handlers::handle_closed_message(
+                                                        &sub_id, &relay_url, &reason, hold_eose, ndb, pool, subs, map, tracker, grouper, direct, &app_handle,
+                                                    );
+                                                });
+                                            }
+                                            RelayMessage::Count { query_id, count, approximate } => {
+                                                debug!(relay = %relay_url, query_id = %query_id, count = count, "Relay count");
+                                                let responses = COUNT_TRACKER.with(|t| {
+                                                    t.borrow_mut().relay_count(&query_id, &relay_url, count, approximate)
+                                                });
+                                                for response in responses {
+                                                    let _ = app_handle.emit("nostr_event", response);
+                                                }
+                                            }
+                                            RelayMessage::Auth { challenge } => {
+                                                let request = RELAY_AUTH.with(|a| a.borrow_mut().challenge(&relay_url, &challenge));
+                                                if let Some(request) = request {
+                                                    let _ = app_handle.emit("nostr_event", request);
+                                                }
+                                            }
+                                            RelayMessage::Ok { event_id, accepted, message } => {
+                                                // OK for our own NIP-42 AUTH event
+                                                if RELAY_AUTH.with(|a| a.borrow().is_pending(&event_id)) {
+                                                    let retry = RELAY_AUTH.with(|a| a.borrow_mut().auth_ok(&event_id, accepted, &message));
+                                                    let _ = app_handle.emit("nostr_event", NostrResponse::AuthResult {
+                                                        relay: relay_url.clone(),
+                                                        accepted,
+                                                        message: message.into_owned(),
+                                                    });
+                                                    if let Some(retry) = retry {
+                                                        retry_after_auth(retry, ndb, pool, &app_handle);
+                                                    }
+                                                    return;
+                                                }
+
+                                                let auth_required = !accepted
+                                                    && subscription_handlers::message_prefix(&message) == Some("auth-required");
+                                                if accepted {
+                                                    debug!(relay = %relay_url, event_id = %event_id, "Event accepted");
+                                                } else {
+                                                    warn!(relay = %relay_url, event_id = %event_id, reason = %message, "Event rejected by relay");
+                                                }
+                                                if auth_required {
+                                                    // Keep it queued - it is re-sent once we authenticate
+                                                    let request = RELAY_AUTH.with(|a| a.borrow_mut().event_needs_auth(&relay_url));
+                                                    if let Some(request) = request {
+                                                        let _ = app_handle.emit("nostr_event", request);
                                                     }
+                                                } else {
+                                                    OUTBOX.with(|o| {
+                                                        if let Some(outbox) = o.borrow_mut().as_mut() {
+                                                            outbox.remove(&event_id, &relay_url);
+                                                        }
+                                                    });
+                                                }
+                                                let results = PUBLISH_TRACKER.with(|t| {
+                                                    t.borrow_mut().relay_ok(&event_id, &relay_url, accepted, &message)
+                                                });
+                                                for result in results {
+                                                    let _ = app_handle.emit("nostr_event", result);
                                                 }
                                             }
                                         }
@@ -181,21 +358,29 @@ pub fn nostr_thread(rx: &Receiver<NostrRequest>, db_path: &str, app_handle: taur
                             ewebsock::WsEvent::Opened => {
                                 info!(relay = %relay_url, "Relay connection opened");
                                 // Status already set by pool.try_recv()
-                                let _ = app_handle.emit("nostr_event", serde_json::json!({
-                                    "type": "relayConnected",
-                                    "relay": relay_url
-                                }));
+                                OUTBOX.with(|o| {
+                                    if let Some(outbox) = o.borrow_mut().as_mut() {
+                                        relay_handlers::flush_outbox(pool, outbox, &relay_url);
+                                    }
+                                });
+                                let _ = app_handle.emit("nostr_event", NostrResponse::RelayConnected {
+                                    relay: relay_url.clone(),
+                                });
                             }
                             ewebsock::WsEvent::Closed => {
                                 info!(relay = %relay_url, "Disconnected");
                                 // Status already set by pool.try_recv()
-                                let _ = app_handle.emit("nostr_event", serde_json::json!({
-                                    "type": "relayDisconnected",
-                                    "relay": relay_url
-                                }));
+                                relay_gone(&relay_url, pool, &app_handle);
+                                let _ = app_handle.emit("nostr_event", NostrResponse::RelayDisconnected {
+                                    relay: relay_url.clone(),
+                                });
                             }
                             ewebsock::WsEvent::Error(e) => {
                                 error!(relay = %relay_url, error = %e, "Relay error");
+                                let _ = app_handle.emit("nostr_event", NostrResponse::RelayError {
+                                    relay: relay_url.clone(),
+                                    error: e.to_string(),
+                                });
                             }
                             _ => {
                                 // Ignore other message types (Binary, Ping, Pong, Unknown)
@@ -205,153 +390,668 @@ pub fn nostr_thread(rx: &Receiver<NostrRequest>, db_path: &str, app_handle: taur
                 }
         });
 
-        // Process commands
-        match rx.try_recv() {
-            Ok(NostrRequest::Init) => {
-                had_activity = true;
-                let _ = app_handle.emit("nostr_event", NostrResponse::Ready);
-            }
-            Ok(NostrRequest::AddRelay { url }) => {
-                had_activity = true;
-                POOL.with(|p| {
-                    if let Some(pool) = p.borrow_mut().as_mut() {
-                        relay_handlers::handle_add_relay(pool, url, &app_handle);
+        // Emit notes matched by active nostrdb subscriptions
+        let mut drained = true;
+        NDB.with(|n| {
+            SUBSCRIPTIONS.with(|subs| {
+                DELIVERED.with(|delivered| {
+                    if let Some(ndb) = n.borrow().as_ref() {
+                        let poll = subscription_handlers::poll_subscriptions(ndb, &subs.borrow(), &mut delivered.borrow_mut(), &app_handle);
+                        had_activity |= poll.emitted;
+                        drained = poll.drained;
                     }
                 });
+            });
+        });
+
+        // Relay EOSEs held until the events before them were stored and polled
+        if INGEST.with(|i| i.borrow().has_held()) && release_held_eoses(drained, &app_handle) {
+            had_activity = true;
+        }
+
+        // Send merged REQs for groupable subscriptions
+        NDB.with(|n| {
+            POOL.with(|p| {
+                if let (Some(ndb), Some(pool)) = (n.borrow_mut().as_mut(), p.borrow_mut().as_mut()) {
+                    with_sub_state(|subs, map, tracker, grouper, direct| {
+                        subscription_handlers::flush_groups(ndb, pool, subs, map, tracker, grouper, direct, &app_handle);
+                    });
+                }
+            });
+        });
+
+        // Retry queued outbox events on connected relays once their backoff passes
+        POOL.with(|p| {
+            OUTBOX.with(|o| {
+                if let (Some(pool), Some(outbox)) = (p.borrow_mut().as_mut(), o.borrow_mut().as_mut()) {
+                    let connected = relay_handlers::connected_relay_urls(pool);
+                    for relay_url in outbox.relays() {
+                        if connected.contains(&relay_url) {
+                            relay_handlers::flush_outbox(pool, outbox, &relay_url);
+                        }
+                    }
+                    outbox.save_if_due(std::time::Instant::now());
+                }
+            });
+        });
+
+        // Report publishes that some relays never answered
+        for result in PUBLISH_TRACKER.with(|t| t.borrow_mut().expire(std::time::Instant::now())) {
+            let _ = app_handle.emit("nostr_event", result);
+        }
+
+        // Report counts with the relay replies that arrived in time
+        for result in COUNT_TRACKER.with(|t| t.borrow_mut().expire(std::time::Instant::now())) {
+            let _ = app_handle.emit("nostr_event", result);
+        }
+
+        // Send EOSE for subscriptions whose relays didn't answer in time
+        let expired = EOSE_TRACKER.with(|t| t.borrow_mut().expire(std::time::Instant::now()));
+        if !expired.is_empty() {
+            POOL.with(|p| {
+                if let Some(pool) = p.borrow_mut().as_mut() {
+                    finish_eose_all(expired, pool, &app_handle);
+                }
+            });
+        }
+
+        // Process queued commands in bounded batches so relay traffic is interleaved
+        for _ in 0..COMMAND_BATCH {
+            match rx.try_recv() {
+                Ok(command) => {
+                    had_activity = true;
+                    metrics.dequeued(command.queued_at.elapsed());
+                    if !handle_command(command.request, command.channel, wakeup, db_path, metrics, checkpoint, &app_handle) {
+                        return;
+                    }
+                }
+                Err(std::sync::mpsc::TryRecvError::Empty) => break,
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => return,
             }
-            Ok(NostrRequest::GetRelayStatus { id }) => {
-                had_activity = true;
-                debug!(id = %id, "GetRelayStatus request");
-                POOL.with(|p| {
-                    if let Some(pool) = p.borrow().as_ref() {
-                        debug!(count = pool.relays.len(), "Relay pool status");
-                        let statuses: Vec<RelayStatusInfo> = pool.relays.iter().map(|relay| {
-                            let url = relay.url().to_string();
-                            let enostr_status = relay.status();
-                            // Map to NDK status values: CONNECTED=5, CONNECTING=4, DISCONNECTED=1
-                            let ndk_status = match enostr_status {
-                                enostr::RelayStatus::Connected => 5,
-                                enostr::RelayStatus::Connecting => 4,
-                                enostr::RelayStatus::Disconnected => 1,
-                            };
-                            debug!(relay = %url, enostr_status = ?enostr_status, ndk_status = ndk_status, "Relay status");
-                            RelayStatusInfo {
-                                url,
-                                status: ndk_status,
-                            }
-                        }).collect();
+        }
 
-                        debug!(count = statuses.len(), "Emitting relay status");
-                        let _ = app_handle.emit("nostr_event", NostrResponse::RelayStatus {
-                            id,
-                            relay_statuses: statuses,
-                        });
+        // Forget checkpointed subscriptions that were closed (e.g. closeOnEose)
+        if had_activity {
+            SUBSCRIPTIONS.with(|subs| {
+                DIRECT_SUBS.with(|direct| {
+                    let subs = subs.borrow();
+                    let direct = direct.borrow();
+                    let mut checkpoint = checkpoint.lock();
+                    if checkpoint.subscription_count() != subs.len() + direct.active_count() {
+                        checkpoint.retain_subscriptions(|id| subs.contains_key(id) || direct.is_active(id));
+                        DELIVERED.with(|d| d.borrow_mut().retain(|id| subs.contains_key(id) || direct.is_active(id)));
                     }
                 });
-            }
-            Ok(NostrRequest::Subscribe { id, filters, subscribe_opts }) => {
-                had_activity = true;
-                NDB.with(|n| {
-                    POOL.with(|p| {
-                        SUBSCRIPTIONS.with(|subs| {
-                            SUB_ID_MAP.with(|map| {
-                                if let (Some(ndb), Some(pool)) = (n.borrow().as_ref(), p.borrow_mut().as_mut()) {
-                                    subscription_handlers::handle_subscribe(
-                                        id,
-                                        filters,
-                                        subscribe_opts,
-                                        ndb,
-                                        pool,
-                                        &mut subs.borrow_mut(),
-                                        &mut map.borrow_mut(),
-                                        &app_handle,
-                                    );
-                                }
-                            });
+            });
+        }
+
+        // Block until there is work: relay traffic, a command, nostrdb notes,
+        // or the next EOSE/publish/count timeout or group flush
+        if !had_activity {
+            let now = std::time::Instant::now();
+            let next_deadline = [
+                EOSE_TRACKER.with(|t| t.borrow().next_deadline()),
+                PUBLISH_TRACKER.with(|t| t.borrow().next_deadline()),
+                COUNT_TRACKER.with(|t| t.borrow().next_deadline()),
+                SUB_GROUPER.with(|g| g.borrow().next_flush()),
+                OUTBOX.with(|o| o.borrow().as_ref().and_then(|outbox| outbox.next_save())),
+            ]
+            .into_iter()
+            .flatten()
+            .min();
+            // nostrdb doesn't wake us for stored notes no subscription matched
+            let max_wait = if INGEST.with(|i| i.borrow().has_held()) { INGEST_POLL } else { MAX_IDLE_WAIT };
+            let timeout = next_deadline
+                .map(|deadline| deadline.saturating_duration_since(now))
+                .unwrap_or(max_wait)
+                .min(max_wait);
+            wakeup.wait(timeout);
+        }
+    }
+}
+
+fn subscribe(
+    id: String,
+    filters: Vec<serde_json::Value>,
+    subscribe_opts: Option<crate::nostr_types::SubscribeOpts>,
+    cached_channel: Option<Channel<CachedEvents>>,
+    app_handle: &tauri::AppHandle,
+) {
+    NDB.with(|n| {
+        POOL.with(|p| {
+            if let (Some(ndb), Some(pool)) = (n.borrow_mut().as_mut(), p.borrow_mut().as_mut()) {
+                with_sub_state(|subs, map, tracker, grouper, direct| {
+                    SEARCH.with(|search| {
+                        DELIVERED.with(|delivered| {
+                            subscription_handlers::handle_subscribe(
+                                id,
+                                filters,
+                                subscribe_opts,
+                                ndb,
+                                pool,
+                                subs,
+                                map,
+                                tracker,
+                                grouper,
+                                &search.borrow(),
+                                direct,
+                                &mut delivered.borrow_mut(),
+                                cached_channel.as_ref(),
+                                app_handle,
+                            );
                         });
                     });
                 });
             }
-            Ok(NostrRequest::Publish { id, event, publish_opts }) => {
-                had_activity = true;
-                NDB.with(|n| {
-                    POOL.with(|p| {
-                        if let (Some(ndb), Some(pool)) = (n.borrow().as_ref(), p.borrow_mut().as_mut()) {
-                            subscription_handlers::handle_publish(id, event, publish_opts, ndb, pool, &app_handle);
-                        }
-                    });
-                });
+        });
+    });
+}
+
+/// Re-add checkpointed relays and re-send subscriptions after a panic restart
+fn restore_checkpoint(checkpoint: &SharedCheckpoint, wakeup: &Wakeup, app_handle: &tauri::AppHandle) {
+    let (relays, subscriptions, auth_policies, search_relays) = {
+        let checkpoint = checkpoint.lock();
+        (checkpoint.relays(), checkpoint.subscriptions(), checkpoint.auth_policies(), checkpoint.search_relays())
+    };
+    warn!(relays = relays.len(), subscriptions = subscriptions.len(), "Restoring relays and subscriptions after restart");
+
+    RELAY_AUTH.with(|a| {
+        let mut auth = a.borrow_mut();
+        for (relay, policy) in auth_policies {
+            auth.set_policy(&relay, policy);
+        }
+    });
+    SEARCH.with(|s| s.borrow_mut().set_relays(search_relays));
+
+    POOL.with(|p| {
+        if let Some(pool) = p.borrow_mut().as_mut() {
+            for url in &relays {
+                relay_handlers::handle_add_relay(pool, url.clone(), wakeup, app_handle);
             }
-            Ok(NostrRequest::Unsubscribe { id }) => {
-                had_activity = true;
-                POOL.with(|p| {
-                    SUBSCRIPTIONS.with(|subs| {
-                        if let Some(pool) = p.borrow_mut().as_mut() {
-                            subscription_handlers::handle_unsubscribe(id, pool, &mut subs.borrow_mut());
+        }
+    });
+
+    let subscription_count = subscriptions.len();
+    for (id, saved) in subscriptions {
+        // Out of the checkpoint while replayed: one that panics again is dropped
+        // instead of crash-looping the thread
+        checkpoint.lock().remove_subscription(&id);
+        subscribe(id.clone(), saved.filters.clone(), saved.opts.clone(), None, app_handle);
+        checkpoint.lock().add_subscription(&id, &saved.filters, &saved.opts);
+    }
+
+    let _ = app_handle.emit("nostr_event", NostrResponse::Restarted {
+        relays: relays.len(),
+        subscriptions: subscription_count,
+    });
+}
+
+fn reset_subscription_state() {
+    // Close the old websockets, write pending outbox changes, and close the
+    // LMDB environment, which can't be opened twice in one process
+    if POOL.with(|p| p.borrow_mut().take()).is_some() {
+        info!("Dropped relay pool from previous run");
+    }
+    OUTBOX.with(|o| o.borrow_mut().take());
+    if NDB.with(|n| n.borrow_mut().take()).is_some() {
+        info!("Closed nostrdb from previous run");
+    }
+
+    SUBSCRIPTIONS.with(|s| s.borrow_mut().clear());
+    SUB_ID_MAP.with(|m| m.borrow_mut().clear());
+    EOSE_TRACKER.with(|t| *t.borrow_mut() = EoseTracker::default());
+    SUB_GROUPER.with(|g| *g.borrow_mut() = SubscriptionGrouper::default());
+    PUBLISH_TRACKER.with(|t| *t.borrow_mut() = PublishTracker::default());
+    RELAY_AUTH.with(|a| *a.borrow_mut() = RelayAuth::default());
+    COUNT_TRACKER.with(|t| *t.borrow_mut() = CountTracker::default());
+    SEARCH.with(|s| *s.borrow_mut() = SearchState::default());
+    DIRECT_SUBS.with(|d| *d.borrow_mut() = DirectSubscriptions::default());
+    SEEN_EVENTS.with(|s| *s.borrow_mut() = SeenEvents::default());
+    DELIVERED.with(|d| *d.borrow_mut() = DeliveredEvents::default());
+    INGEST.with(|i| *i.borrow_mut() = IngestTracker::default());
+}
+
+/// Whether a relay-level sub id (a subscription or any member of a group) is
+/// still waiting for the relay's EOSE
+fn waits_for_eose(relay_sub_id: &str, relay_url: &str) -> bool {
+    EOSE_TRACKER.with(|t| {
+        let tracker = t.borrow();
+        match SUB_GROUPER.with(|g| g.borrow().members(relay_sub_id).cloned()) {
+            Some(members) => members.iter().any(|m| tracker.waits_on(m, relay_url)),
+            None => tracker.waits_on(relay_sub_id, relay_url),
+        }
+    })
+}
+
+/// Handle held relay EOSEs whose events nostrdb has stored and the last poll
+/// delivered. Returns true if any were handled.
+fn release_held_eoses(drained: bool, app_handle: &tauri::AppHandle) -> bool {
+    NDB.with(|n| {
+        POOL.with(|p| {
+            let (Some(ndb), Some(pool)) = (n.borrow_mut().as_mut(), p.borrow_mut().as_mut()) else {
+                return false;
+            };
+            let released = {
+                let Ok(txn) = nostrdb::Transaction::new(ndb) else {
+                    return false;
+                };
+                INGEST.with(|i| {
+                    i.borrow_mut().release(
+                        drained,
+                        |id| ndb.get_notekey_by_id(&txn, id).is_ok(),
+                        waits_for_eose,
+                    )
+                })
+            };
+            if released.is_empty() {
+                return false;
+            }
+            with_sub_state(|subs, map, tracker, grouper, direct| {
+                for (relay_url, sub_id) in released {
+                    subscription_handlers::handle_eose_message(
+                        &sub_id, &relay_url, ndb, pool, subs, map, tracker, grouper, direct, app_handle,
+                    );
+                }
+            });
+            true
+        })
+    })
+}
+
+/// Emit an event nostrdb already has to the subscriptions behind a relay sub id
+/// that haven't received it, e.g. ones whose cache query ran before it was stored
+fn emit_stored(
+    ndb: &Ndb,
+    relay_sub_id: &str,
+    event_id: &[u8; 32],
+    relay_url: &str,
+    app_handle: &tauri::AppHandle,
+) {
+    let members = SUB_GROUPER.with(|g| g.borrow().members(relay_sub_id).cloned());
+    let is_group = members.is_some();
+    let targets: Vec<String> = members
+        .unwrap_or_else(|| vec![relay_sub_id.to_string()])
+        .into_iter()
+        .filter(|sub_id| {
+            let active = SUBSCRIPTIONS.with(|s| s.borrow().contains_key(sub_id))
+                || DIRECT_SUBS.with(|d| d.borrow().is_active(sub_id));
+            active && !DELIVERED.with(|d| d.borrow().contains(sub_id, event_id))
+        })
+        .collect();
+    if targets.is_empty() {
+        return;
+    }
+
+    let Ok(txn) = nostrdb::Transaction::new(ndb) else {
+        return;
+    };
+    let Ok(note) = ndb.get_note_by_id(&txn, event_id) else {
+        return;
+    };
+    let Some(event) = subscription_handlers::note_event(&note) else {
+        return;
+    };
+
+    for sub_id in targets {
+        // A group REQ merges its members' filters, so check the member's own
+        if is_group && !SUB_GROUPER.with(|g| g.borrow().member_matches(&sub_id, &note)) {
+            continue;
+        }
+        DELIVERED.with(|d| d.borrow_mut().first_delivery(&sub_id, event_id));
+        debug!(sub_id = %sub_id, relay = %relay_url, "Emitting stored event to subscription");
+        let _ = app_handle.emit("nostr_event", NostrResponse::Event {
+            sub_id,
+            event: event.clone(),
+            relay: Some(relay_url.to_string()),
+        });
+    }
+}
+
+/// Filters of a relay-level REQ per subscription; group REQs expand to their members
+fn closed_subs(relay_sub_id: &str, checkpoint: &SharedCheckpoint) -> Vec<ClosedSub> {
+    let sub_ids = SUB_GROUPER
+        .with(|g| g.borrow().members(relay_sub_id).cloned())
+        .unwrap_or_else(|| vec![relay_sub_id.to_string()]);
+    let checkpoint = checkpoint.lock();
+    sub_ids
+        .into_iter()
+        .filter_map(|sub_id| {
+            let saved = checkpoint.subscription(&sub_id)?;
+            Some(ClosedSub { sub_id, filters: saved.filters })
+        })
+        .collect()
+}
+
+/// Re-send REQs and queued events that a relay refused before we authenticated.
+/// If the relay rejected our AUTH, release the EOSE held for its closed REQs.
+fn retry_after_auth(retry: AuthRetry, ndb: &mut Ndb, pool: &mut RelayPool, app_handle: &tauri::AppHandle) {
+    if !retry.accepted {
+        release_held_eose(&retry.relay, retry.subs, ndb, pool, app_handle);
+        return;
+    }
+
+    for sub in retry.subs {
+        let active = SUBSCRIPTIONS.with(|s| s.borrow().contains_key(&sub.sub_id))
+            || DIRECT_SUBS.with(|d| d.borrow().is_active(&sub.sub_id));
+        if !active {
+            continue;
+        }
+        debug!(sub_id = %sub.sub_id, relay = %retry.relay, "Re-sending REQ after auth");
+        pool.send_to(&subscription_handlers::raw_message("REQ", &sub.sub_id, &sub.filters), &retry.relay);
+    }
+
+    OUTBOX.with(|o| {
+        if let Some(outbox) = o.borrow_mut().as_mut() {
+            outbox.retry_now(&retry.relay);
+            relay_handlers::flush_outbox(pool, outbox, &retry.relay);
+        }
+    });
+}
+
+/// Count a relay as done for subscriptions whose auth-required: CLOSED was held
+fn release_held_eose(
+    relay_url: &str,
+    subs: Vec<ClosedSub>,
+    ndb: &mut Ndb,
+    pool: &mut RelayPool,
+    app_handle: &tauri::AppHandle,
+) {
+    with_sub_state(|subscriptions, map, tracker, grouper, direct| {
+        for sub in subs {
+            if tracker.relay_eose(&sub.sub_id, relay_url) {
+                subscription_handlers::finish_eose(sub.sub_id, ndb, pool, subscriptions, map, tracker, grouper, direct, app_handle);
+            }
+        }
+    });
+}
+
+/// Borrow the subscription tables, EOSE tracker, grouper and direct subscriptions together
+fn with_sub_state<R>(
+    f: impl FnOnce(
+        &mut HashMap<String, Subscription>,
+        &mut HashMap<u64, String>,
+        &mut EoseTracker,
+        &mut SubscriptionGrouper,
+        &mut DirectSubscriptions,
+    ) -> R,
+) -> R {
+    SUBSCRIPTIONS.with(|subs| {
+        SUB_ID_MAP.with(|map| {
+            EOSE_TRACKER.with(|tracker| {
+                SUB_GROUPER.with(|grouper| {
+                    DIRECT_SUBS.with(|direct| {
+                        f(
+                            &mut subs.borrow_mut(),
+                            &mut map.borrow_mut(),
+                            &mut tracker.borrow_mut(),
+                            &mut grouper.borrow_mut(),
+                            &mut direct.borrow_mut(),
+                        )
+                    })
+                })
+            })
+        })
+    })
+}
+
+/// Stop waiting on a relay that disconnected or was removed: forget its auth
+/// state and finish subscriptions that were only waiting for its EOSE
+fn relay_gone(relay_url: &str, pool: &mut RelayPool, app_handle: &tauri::AppHandle) {
+    RELAY_AUTH.with(|a| a.borrow_mut().relay_gone(relay_url));
+    let completed = EOSE_TRACKER.with(|t| t.borrow_mut().relay_gone(relay_url));
+    finish_eose_all(completed, pool, app_handle);
+}
+
+/// Emit EOSE for completed subscriptions (relay gone, timeout)
+fn finish_eose_all(sub_ids: Vec<String>, pool: &mut RelayPool, app_handle: &tauri::AppHandle) {
+    NDB.with(|n| {
+        if let Some(ndb) = n.borrow_mut().as_mut() {
+            with_sub_state(|subs, map, tracker, grouper, direct| {
+                for sub_id in sub_ids {
+                    // Closes the shared group REQ too once its last member is done
+                    subscription_handlers::finish_eose(sub_id, ndb, pool, subs, map, tracker, grouper, direct, app_handle);
+                }
+            });
+        }
+    });
+}
+
+/// Handle one frontend command. Returns false when the thread should exit.
+fn handle_command(
+    request: NostrRequest,
+    channel: Option<Channel<CachedEvents>>,
+    wakeup: &Wakeup,
+    db_path: &str,
+    metrics: &CommandMetrics,
+    checkpoint: &SharedCheckpoint,
+    app_handle: &tauri::AppHandle,
+) -> bool {
+    match request {
+        NostrRequest::Init => {
+            let _ = app_handle.emit("nostr_event", NostrResponse::Ready);
+        }
+        NostrRequest::AddRelay { url } => {
+            checkpoint.lock().add_relay(&url);
+            POOL.with(|p| {
+                if let Some(pool) = p.borrow_mut().as_mut() {
+                    relay_handlers::handle_add_relay(pool, url, wakeup, app_handle);
+                }
+            });
+        }
+        NostrRequest::GetRelayStatus { id } => {
+            debug!(id = %id, "GetRelayStatus request");
+            POOL.with(|p| {
+                if let Some(pool) = p.borrow().as_ref() {
+                    debug!(count = pool.relays.len(), "Relay pool status");
+                    let statuses: Vec<RelayStatusInfo> = pool.relays.iter().map(|relay| {
+                        let url = relay.url().to_string();
+                        let enostr_status = relay.status();
+                        // Map to NDK status values: CONNECTED=5, CONNECTING=4, DISCONNECTED=1
+                        let ndk_status = match enostr_status {
+                            enostr::RelayStatus::Connected => 5,
+                            enostr::RelayStatus::Connecting => 4,
+                            enostr::RelayStatus::Disconnected => 1,
+                        };
+                        debug!(relay = %url, enostr_status = ?enostr_status, ndk_status = ndk_status, "Relay status");
+                        RelayStatusInfo {
+                            url,
+                            status: ndk_status,
                         }
+                    }).collect();
+
+                    debug!(count = statuses.len(), "Emitting relay status");
+                    let _ = app_handle.emit("nostr_event", NostrResponse::RelayStatus {
+                        id,
+                        relay_statuses: statuses,
                     });
-                });
-            }
-            Ok(NostrRequest::RemoveRelay { url }) => {
-                had_activity = true;
+                }
+            });
+        }
+        NostrRequest::Subscribe { id, mut filters, subscribe_opts } => {
+            filters.iter_mut().for_each(normalize_filter);
+            let (saved_filters, saved_opts) = (filters.clone(), subscribe_opts.clone());
+            subscribe(id.clone(), filters, subscribe_opts, channel, app_handle);
+            // Checkpointed once handled, so a Subscribe that panics isn't replayed on restart
+            checkpoint.lock().add_subscription(&id, &saved_filters, &saved_opts);
+        }
+        NostrRequest::Publish { id, event, publish_opts } => {
+            NDB.with(|n| {
                 POOL.with(|p| {
-                    if let Some(pool) = p.borrow_mut().as_mut() {
-                        relay_handlers::handle_remove_relay(pool, url);
+                    if let (Some(ndb), Some(pool)) = (n.borrow().as_ref(), p.borrow_mut().as_mut()) {
+                        PUBLISH_TRACKER.with(|tracker| {
+                            OUTBOX.with(|o| {
+                                INVALID_EVENTS.with(|invalid| {
+                                    if let Some(outbox) = o.borrow_mut().as_mut() {
+                                        subscription_handlers::handle_publish(
+                                            id,
+                                            event,
+                                            publish_opts,
+                                            ndb,
+                                            pool,
+                                            &mut tracker.borrow_mut(),
+                                            outbox,
+                                            &mut invalid.borrow_mut(),
+                                            app_handle,
+                                        );
+                                    }
+                                });
+                            });
+                        });
                     }
                 });
-            }
-            Ok(NostrRequest::ConnectRelay { url }) => {
-                had_activity = true;
+            });
+        }
+        NostrRequest::Unsubscribe { id } => {
+            checkpoint.lock().remove_subscription(&id);
+            DELIVERED.with(|d| d.borrow_mut().remove(&id));
+            NDB.with(|n| {
                 POOL.with(|p| {
-                    if let Some(pool) = p.borrow_mut().as_mut() {
-                        relay_handlers::handle_connect_relay(pool, url);
+                    if let (Some(ndb), Some(pool)) = (n.borrow_mut().as_mut(), p.borrow_mut().as_mut()) {
+                        with_sub_state(|subs, map, tracker, grouper, direct| {
+                            subscription_handlers::handle_unsubscribe(id, ndb, pool, subs, map, tracker, grouper, direct);
+                        });
                     }
                 });
-            }
-            Ok(NostrRequest::DisconnectRelay { url }) => {
-                had_activity = true;
-                POOL.with(|p| {
-                    if let Some(pool) = p.borrow_mut().as_mut() {
-                        relay_handlers::handle_disconnect_relay(pool, url);
+            });
+        }
+        NostrRequest::RemoveRelay { url } => {
+            checkpoint.lock().remove_relay(&url);
+            OUTBOX.with(|o| {
+                if let Some(outbox) = o.borrow_mut().as_mut() {
+                    outbox.remove_relay(&url);
+                }
+            });
+            POOL.with(|p| {
+                if let Some(pool) = p.borrow_mut().as_mut() {
+                    relay_gone(&url, pool, app_handle);
+                    // Publishes still waiting on it can't be retried there
+                    for result in PUBLISH_TRACKER.with(|t| t.borrow_mut().relay_removed(&url)) {
+                        let _ = app_handle.emit("nostr_event", result);
                     }
-                });
-            }
-            Ok(NostrRequest::ReconnectDisconnected { reason }) => {
-                had_activity = true;
+                    relay_handlers::handle_remove_relay(pool, url, app_handle);
+                }
+            });
+        }
+        NostrRequest::ConnectRelay { url } => {
+            POOL.with(|p| {
+                if let Some(pool) = p.borrow_mut().as_mut() {
+                    relay_handlers::handle_connect_relay(pool, url);
+                }
+            });
+        }
+        NostrRequest::DisconnectRelay { url } => {
+            POOL.with(|p| {
+                if let Some(pool) = p.borrow_mut().as_mut() {
+                    relay_handlers::handle_disconnect_relay(pool, url);
+                }
+            });
+        }
+        NostrRequest::ReconnectDisconnected { reason } => {
+            POOL.with(|p| {
+                if let Some(pool) = p.borrow_mut().as_mut() {
+                    relay_handlers::handle_reconnect_disconnected(pool, reason);
+                }
+            });
+        }
+        NostrRequest::GetStats { id } => {
+            NDB.with(|n| {
+                if let Some(ndb) = n.borrow().as_ref() {
+                    match crate::ndb_stats::collect_stats(ndb, db_path) {
+                        Ok(stats) => {
+                            let _ = app_handle.emit("nostr_event", NostrResponse::Stats {
+                                id: id.clone(),
+                                stats
+                            });
+                        }
+                        Err(e) => {
+                            error!(error = %e, "Failed to collect nostrdb stats");
+                            let _ = app_handle.emit("nostr_event", NostrResponse::Error {
+                                id: Some(id.clone()),
+                                error: e,
+                            });
+                        }
+                    }
+                }
+            });
+        }
+        NostrRequest::GetMetrics { id } => {
+            let _ = app_handle.emit("nostr_event", NostrResponse::Metrics {
+                id,
+                metrics: metrics.snapshot(),
+            });
+        }
+        NostrRequest::GetSeenOn { id, event_id } => {
+            let mut id_bytes = [0u8; 32];
+            let relays = match hex::decode_to_slice(&event_id, &mut id_bytes) {
+                Ok(()) => SEEN_EVENTS.with(|s| s.borrow().relays(&id_bytes)),
+                Err(_) => Vec::new(),
+            };
+            let _ = app_handle.emit("nostr_event", NostrResponse::SeenOn { id, event_id, relays });
+        }
+        NostrRequest::LoadOlder { id, mut filters, until, limit } => {
+            filters.iter_mut().for_each(normalize_filter);
+            NDB.with(|n| {
+                if let Some(ndb) = n.borrow().as_ref() {
+                    subscription_handlers::handle_load_older(id, filters, until, limit, ndb, app_handle);
+                }
+            });
+        }
+        NostrRequest::Count { id, mut filters } => {
+            filters.iter_mut().for_each(normalize_filter);
+            NDB.with(|n| {
                 POOL.with(|p| {
-                    if let Some(pool) = p.borrow_mut().as_mut() {
-                        relay_handlers::handle_reconnect_disconnected(pool, reason);
+                    if let (Some(ndb), Some(pool)) = (n.borrow().as_ref(), p.borrow_mut().as_mut()) {
+                        COUNT_TRACKER.with(|t| {
+                            subscription_handlers::handle_count(id, filters, ndb, pool, &mut t.borrow_mut(), app_handle);
+                        });
                     }
                 });
+            });
+        }
+        NostrRequest::Auth { relay, event } => {
+            match RELAY_AUTH.with(|a| a.borrow_mut().prepare_response(&relay, &event)) {
+                Ok(event_id) => {
+                    debug!(relay = %relay, event_id = %event_id, "Sending AUTH");
+                    let msg = ClientMessage::Raw(serde_json::json!(["AUTH", event]).to_string());
+                    POOL.with(|p| {
+                        if let Some(pool) = p.borrow_mut().as_mut() {
+                            pool.send_to(&msg, &relay);
+                        }
+                    });
+                }
+                Err(e) => {
+                    warn!(relay = %relay, error = %e, "Invalid AUTH response");
+                    let _ = app_handle.emit("nostr_event", NostrResponse::Error { id: None, error: e });
+                }
             }
-            Ok(NostrRequest::GetStats { id }) => {
-                had_activity = true;
-                use crate::nostr_types::LocalDataStats;
-                use std::collections::HashMap;
-
-                // nostrdb doesn't expose count API directly - return zeros for now
-                // Can be improved later with ndb_stat bindings
-                let stats = LocalDataStats {
-                    total_events: 0,
-                    events_by_kind: HashMap::new(),
-                };
-
-                let _ = app_handle.emit("nostr_event", NostrResponse::Stats {
-                    id: id.clone(),
-                    stats
+        }
+        NostrRequest::DeclineAuth { relay } => {
+            // Release the EOSE held for REQs the relay closed with auth-required:
+            if let Some(retry) = RELAY_AUTH.with(|a| a.borrow_mut().decline(&relay)) {
+                NDB.with(|n| {
+                    POOL.with(|p| {
+                        if let (Some(ndb), Some(pool)) = (n.borrow_mut().as_mut(), p.borrow_mut().as_mut()) {
+                            retry_after_auth(retry, ndb, pool, app_handle);
+                        }
+                    });
                 });
             }
-            Ok(NostrRequest::Close) => {
-                info!("Close command received");
-                break;
-            }
-            Err(std::sync::mpsc::TryRecvError::Empty) => {},
-            Err(std::sync::mpsc::TryRecvError::Disconnected) => break,
         }
-
-        // Sleep when idle to reduce CPU usage
-        if !had_activity {
-            std::thread::sleep(std::time::Duration::from_millis(100));
+        NostrRequest::SetAuthPolicy { relay, policy } => {
+            info!(relay = %relay, policy = ?policy, "Setting relay auth policy");
+            checkpoint.lock().set_auth_policy(&relay, policy);
+            RELAY_AUTH.with(|a| a.borrow_mut().set_policy(&relay, policy));
+        }
+        NostrRequest::SetSearchRelays { urls } => {
+            checkpoint.lock().set_search_relays(&urls);
+            SEARCH.with(|s| s.borrow_mut().set_relays(urls));
+        }
+        NostrRequest::Close => {
+            info!("Close command received");
+            // Write pending outbox bookkeeping before the thread exits
+            OUTBOX.with(|o| o.borrow_mut().take());
+            return false;
         }
     }
+    true
 }
@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use ts_rs::TS;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "camelCase")]
@@ -40,15 +42,68 @@ pub enum NostrRequest {
     GetStats {
         id: String,
     },
+    GetMetrics {
+        id: String,
+    },
+    /// "Load older": a page of cached events created at or before `until`, from nostrdb only
+    LoadOlder {
+        id: String,
+        filters: Vec<serde_json::Value>,
+        until: u64,
+        limit: Option<u32>,
+    },
+    /// NIP-45 count, answered from nostrdb and then from relays
+    Count {
+        id: String,
+        filters: Vec<serde_json::Value>,
+    },
+    /// Signed NIP-42 AUTH event answering a relay's challenge
+    Auth {
+        relay: String,
+        event: serde_json::Value,
+    },
+    /// The frontend won't answer a relay's AuthRequest (declined, no signer)
+    DeclineAuth {
+        relay: String,
+    },
+    SetAuthPolicy {
+        relay: String,
+        policy: AuthPolicy,
+    },
+    /// Relays that accept NIP-50 `search` filters (replaces the previous set)
+    SetSearchRelays {
+        urls: Vec<String>,
+    },
+    /// Relays that have delivered an event ("seen on")
+    GetSeenOn {
+        id: String,
+        #[serde(rename = "eventId")]
+        event_id: String,
+    },
     Close,
 }
 
+/// When to authenticate to a relay that sends a NIP-42 challenge
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthPolicy {
+    Always,
+    #[default]
+    Ask,
+    Never,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubscribeOpts {
     pub destinations: Option<Vec<String>>,
     #[serde(rename = "closeOnEose")]
     pub close_on_eose: Option<bool>,
     pub groupable: Option<bool>,
+    #[serde(rename = "eoseTimeoutMs")]
+    pub eose_timeout_ms: Option<u64>,
+    /// Max cached events to load, instead of the filters' own limits
+    #[serde(rename = "cacheLimit")]
+    pub cache_limit: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -60,14 +115,17 @@ pub struct PublishOpts {
     pub source: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum NostrResponse {
     Ready,
     Event {
         #[serde(rename = "subId")]
         sub_id: String,
-        event: serde_json::Value,
+        /// The event JSON as received or as stored by nostrdb, never re-parsed
+        #[ts(as = "serde_json::Value")]
+        event: Box<RawValue>,
         relay: Option<String>,
     },
     Eose {
@@ -77,10 +135,26 @@ pub enum NostrResponse {
     Published {
         id: String,
     },
+    PublishResult {
+        id: String,
+        #[serde(rename = "eventId")]
+        event_id: String,
+        accepted: Vec<String>,
+        rejected: Vec<RelayRejection>,
+        #[serde(rename = "timedOut")]
+        timed_out: Vec<String>,
+    },
     Error {
         id: Option<String>,
         error: String,
     },
+    InvalidEvent {
+        id: String,
+        #[serde(rename = "eventId")]
+        event_id: Option<String>,
+        source: Option<String>,
+        error: String,
+    },
     RelayStatus {
         id: String,
         #[serde(rename = "relayStatuses")]
@@ -90,17 +164,167 @@ pub enum NostrResponse {
         id: String,
         stats: LocalDataStats,
     },
+    Metrics {
+        id: String,
+        metrics: CommandQueueStats,
+    },
+    /// The nostr thread recovered from a panic and restored this state
+    Restarted {
+        relays: usize,
+        subscriptions: usize,
+    },
+    RelayAdded {
+        relay: String,
+    },
+    RelayRemoved {
+        relay: String,
+    },
+    RelayConnected {
+        relay: String,
+    },
+    RelayDisconnected {
+        relay: String,
+    },
+    RelayError {
+        relay: String,
+        error: String,
+    },
+    Notice {
+        relay: String,
+        notice: String,
+    },
+    /// A relay ended a subscription with NIP-01 CLOSED. `prefix` is the
+    /// machine-readable part of the reason, e.g. "auth-required" or "rate-limited".
+    Closed {
+        #[serde(rename = "subId")]
+        sub_id: String,
+        relay: String,
+        reason: String,
+        prefix: Option<String>,
+    },
+    /// Sign a kind-22242 event for this challenge and send it back as `auth`.
+    /// `ask` means the relay's policy wants the user to confirm first.
+    AuthRequest {
+        relay: String,
+        challenge: String,
+        ask: bool,
+    },
+    AuthResult {
+        relay: String,
+        accepted: bool,
+        message: String,
+    },
+    /// One count source answered: nostrdb when `relay` is None, otherwise a relay.
+    /// `estimate` is the best count so far across all sources.
+    Count {
+        id: String,
+        relay: Option<String>,
+        #[ts(type = "number")]
+        count: u64,
+        approximate: bool,
+        #[ts(type = "number")]
+        estimate: u64,
+    },
+    CountDone {
+        id: String,
+        #[ts(type = "number")]
+        estimate: u64,
+        relays: Vec<RelayCount>,
+    },
+    /// The cache query for a subscription returned `limit` events, so older ones
+    /// were left out. Page further back with LoadOlder using `oldest` as until.
+    CacheLimitReached {
+        #[serde(rename = "subId")]
+        sub_id: String,
+        limit: u32,
+        #[ts(type = "number | null")]
+        oldest: Option<u64>,
+    },
+    OlderEvents {
+        id: String,
+        #[ts(as = "Vec<serde_json::Value>")]
+        events: Vec<Box<RawValue>>,
+        /// The next page's until. Never repeats events from this page.
+        #[ts(type = "number | null")]
+        until: Option<u64>,
+        /// The page was full, so there may be more
+        more: bool,
+    },
+    /// Relays that delivered the event since it was last evicted from the seen-id cache
+    SeenOn {
+        id: String,
+        #[serde(rename = "eventId")]
+        event_id: String,
+        relays: Vec<String>,
+    },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Cached events for one subscription, sent over its nostr_subscribe channel
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct CachedEvents {
+    #[serde(rename = "subId")]
+    pub sub_id: String,
+    #[ts(as = "Vec<serde_json::Value>")]
+    pub events: Vec<Box<RawValue>>,
+    pub eose: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RelayRejection {
+    pub relay: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RelayCount {
+    pub relay: String,
+    #[ts(type = "number")]
+    pub count: u64,
+    pub approximate: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
 pub struct RelayStatusInfo {
     pub url: String,
     pub status: u8,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
 #[serde(rename_all = "camelCase")]
 pub struct LocalDataStats {
     pub total_events: usize,
     pub events_by_kind: std::collections::HashMap<u32, usize>,
+    /// Events whose kind has no dedicated nostrdb counter
+    pub other_kinds: usize,
+    /// Size of the nostrdb data file in bytes
+    #[ts(type = "number")]
+    pub database_size_bytes: u64,
+    pub index_sizes: Vec<IndexStats>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandQueueStats {
+    pub queue_depth: usize,
+    pub max_queue_depth: usize,
+    #[ts(type = "number")]
+    pub commands_processed: u64,
+    pub avg_latency_ms: f64,
+    pub max_latency_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStats {
+    pub name: String,
+    pub count: usize,
+    pub key_size: usize,
+    pub value_size: usize,
 }
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+// Retry backoff: 2s, 4s, 8s ... capped at 5 minutes
+const BASE_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+// Give up on a relay after this many unanswered sends
+const MAX_ATTEMPTS: u32 = 12;
+// Attempt and OK bookkeeping is written at most this often
+const SAVE_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct QueuedEvent {
+    event_id: String,
+    event_json: String,
+    attempts: u32,
+    next_attempt_ms: u64,
+}
+
+/// Durable per-relay queue of signed events awaiting an OK
+pub struct Outbox {
+    path: PathBuf,
+    queues: HashMap<String, Vec<QueuedEvent>>,
+    // Unsaved changes, and when they became due for writing
+    save_at: Option<Instant>,
+}
+
+impl Outbox {
+    /// Load the outbox from disk, starting empty if missing or unreadable
+    pub fn load(path: &Path) -> Self {
+        let queues = match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                warn!(error = %e, "Corrupt outbox file, starting empty");
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        let outbox = Self { path: path.to_path_buf(), queues, save_at: None };
+        let count = outbox.queued_count();
+        if count > 0 {
+            info!(count = count, "Loaded outbox");
+        }
+        outbox
+    }
+
+    pub fn queued_count(&self) -> usize {
+        self.queues.values().map(|q| q.len()).sum()
+    }
+
+    /// Queue an event for each target relay. Relays in `sent_to` already got
+    /// the event, so their first retry is scheduled after the initial backoff.
+    pub fn enqueue(&mut self, event_id: &str, event_json: &str, relays: &[String], sent_to: &[String]) {
+        let now = now_ms();
+        for relay in relays {
+            let queue = self.queues.entry(relay.clone()).or_default();
+            if queue.iter().any(|q| q.event_id == event_id) {
+                continue;
+            }
+            let (attempts, next_attempt_ms) = if sent_to.contains(relay) {
+                (1, now + backoff(1).as_millis() as u64)
+            } else {
+                (0, 0)
+            };
+            queue.push(QueuedEvent {
+                event_id: event_id.to_string(),
+                event_json: event_json.to_string(),
+                attempts,
+                next_attempt_ms,
+            });
+        }
+        // New events are written right away so a crash can't lose them
+        self.save();
+    }
+
+    /// Take events that are due for a relay, recording the attempt and scheduling the next retry
+    pub fn take_due(&mut self, relay_url: &str) -> Vec<String> {
+        let now = now_ms();
+        let Some(queue) = self.queues.get_mut(relay_url) else {
+            return Vec::new();
+        };
+
+        let mut due = Vec::new();
+        queue.retain_mut(|entry| {
+            if entry.next_attempt_ms > now {
+                return true;
+            }
+            if entry.attempts >= MAX_ATTEMPTS {
+                warn!(relay = %relay_url, event_id = %entry.event_id, "Dropping outbox entry after max attempts");
+                return false;
+            }
+            entry.attempts += 1;
+            entry.next_attempt_ms = now + backoff(entry.attempts).as_millis() as u64;
+            due.push(entry.event_json.clone());
+            true
+        });
+
+        if !due.is_empty() {
+            debug!(relay = %relay_url, count = due.len(), "Outbox entries due");
+            self.mark_dirty();
+        }
+        due
+    }
+
+    /// Make every entry for a relay due now, e.g. after it accepted our AUTH
+    pub fn retry_now(&mut self, relay_url: &str) {
+        if let Some(queue) = self.queues.get_mut(relay_url) {
+            for entry in queue.iter_mut() {
+                entry.next_attempt_ms = 0;
+            }
+        }
+    }
+
+    /// Drop an entry once the relay has answered with OK
+    pub fn remove(&mut self, event_id: &str, relay_url: &str) {
+        let Some(queue) = self.queues.get_mut(relay_url) else {
+            return;
+        };
+        let before = queue.len();
+        queue.retain(|entry| entry.event_id != event_id);
+        if queue.len() == before {
+            return;
+        }
+        if queue.is_empty() {
+            self.queues.remove(relay_url);
+        }
+        self.mark_dirty();
+    }
+
+    /// Drop every entry for a relay that was removed from the pool
+    pub fn remove_relay(&mut self, relay_url: &str) {
+        if let Some(queue) = self.queues.remove(relay_url) {
+            info!(relay = %relay_url, count = queue.len(), "Dropped outbox entries for removed relay");
+            self.save();
+        }
+    }
+
+    /// Relays with at least one queued event
+    pub fn relays(&self) -> Vec<String> {
+        self.queues.keys().cloned().collect()
+    }
+
+    /// When pending changes should be written, if there are any
+    pub fn next_save(&self) -> Option<Instant> {
+        self.save_at
+    }
+
+    /// Write pending changes once SAVE_INTERVAL has passed since the first of them
+    pub fn save_if_due(&mut self, now: Instant) {
+        if self.save_at.is_some_and(|at| at <= now) {
+            self.save();
+        }
+    }
+
+    fn mark_dirty(&mut self) {
+        self.save_at.get_or_insert_with(|| Instant::now() + SAVE_INTERVAL);
+    }
+
+    fn save(&mut self) {
+        self.save_at = None;
+        let json = match serde_json::to_string(&self.queues) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!(error = %e, "Failed to serialize outbox");
+                return;
+            }
+        };
+        // Write then rename so a crash never leaves a truncated file
+        let tmp = self.path.with_extension("tmp");
+        if let Err(e) = std::fs::write(&tmp, json).and_then(|_| std::fs::rename(&tmp, &self.path)) {
+            warn!(error = %e, "Failed to write outbox");
+        }
+    }
+}
+
+impl Drop for Outbox {
+    fn drop(&mut self) {
+        if self.save_at.is_some() {
+            self.save();
+        }
+    }
+}
+
+fn backoff(attempts: u32) -> Duration {
+    let factor = 1u32 << attempts.saturating_sub(1).min(16);
+    (BASE_BACKOFF * factor).min(MAX_BACKOFF)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
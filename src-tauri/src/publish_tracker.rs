@@ -0,0 +1,138 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tracing::debug;
+use crate::nostr_types::{NostrResponse, RelayRejection};
+
+// How long to wait for OK from every relay before reporting the rest as timed out
+pub const PUBLISH_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct PendingPublish {
+    // Every publish request for this event; each gets the result
+    pub_ids: Vec<String>,
+    waiting_on: HashSet<String>,
+    accepted: Vec<String>,
+    rejected: Vec<RelayRejection>,
+    deadline: Instant,
+}
+
+impl PendingPublish {
+    fn into_responses(self, event_id: String) -> Vec<NostrResponse> {
+        let timed_out: Vec<String> = self.waiting_on.into_iter().collect();
+        self.pub_ids
+            .into_iter()
+            .map(|id| NostrResponse::PublishResult {
+                id,
+                event_id: event_id.clone(),
+                accepted: self.accepted.clone(),
+                rejected: self.rejected.clone(),
+                timed_out: timed_out.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Collects relay OK messages per event id into a single publish result
+#[derive(Default)]
+pub struct PublishTracker {
+    pending: HashMap<String, PendingPublish>,
+}
+
+impl PublishTracker {
+    /// Track a publish. Publishing an event that is still pending merges into
+    /// the existing entry, so both requests get the combined result.
+    pub fn start(&mut self, pub_id: String, event_id: String, relays: Vec<String>) {
+        debug!(pub_id = %pub_id, event_id = %event_id, relay_count = relays.len(), "Tracking publish");
+        let deadline = Instant::now() + PUBLISH_TIMEOUT;
+        if let Some(pending) = self.pending.get_mut(&event_id) {
+            let answered: HashSet<&str> = pending.accepted
+                .iter()
+                .map(String::as_str)
+                .chain(pending.rejected.iter().map(|r| r.relay.as_str()))
+                .collect();
+            let new_relays: Vec<String> = relays.into_iter().filter(|r| !answered.contains(r.as_str())).collect();
+            pending.waiting_on.extend(new_relays);
+            pending.pub_ids.push(pub_id);
+            pending.deadline = deadline;
+            return;
+        }
+        self.pending.insert(event_id, PendingPublish {
+            pub_ids: vec![pub_id],
+            waiting_on: relays.into_iter().collect(),
+            accepted: Vec::new(),
+            rejected: Vec::new(),
+            deadline,
+        });
+    }
+
+    /// Record an OK from a relay. Returns the results once every relay has answered.
+    pub fn relay_ok(&mut self, event_id: &str, relay_url: &str, accepted: bool, message: &str) -> Vec<NostrResponse> {
+        let Some(pending) = self.pending.get_mut(event_id) else {
+            return Vec::new();
+        };
+        if !pending.waiting_on.remove(relay_url) {
+            return Vec::new();
+        }
+        if accepted {
+            pending.accepted.push(relay_url.to_string());
+        } else {
+            pending.rejected.push(RelayRejection {
+                relay: relay_url.to_string(),
+                reason: message.to_string(),
+            });
+        }
+        if !pending.waiting_on.is_empty() {
+            return Vec::new();
+        }
+        self.pending
+            .remove(event_id)
+            .map(|pending| pending.into_responses(event_id.to_string()))
+            .unwrap_or_default()
+    }
+
+    /// A relay was removed: count it as rejecting every publish still waiting
+    /// on it. Returns the results of publishes that are now complete.
+    pub fn relay_removed(&mut self, relay_url: &str) -> Vec<NostrResponse> {
+        let mut done = Vec::new();
+        for (event_id, pending) in self.pending.iter_mut() {
+            if pending.waiting_on.remove(relay_url) {
+                pending.rejected.push(RelayRejection {
+                    relay: relay_url.to_string(),
+                    reason: "relay removed".to_string(),
+                });
+                if pending.waiting_on.is_empty() {
+                    done.push(event_id.clone());
+                }
+            }
+        }
+        done.into_iter()
+            .filter_map(|event_id| {
+                let pending = self.pending.remove(&event_id)?;
+                Some(pending.into_responses(event_id))
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Earliest publish timeout, if any publish is waiting
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.values().map(|p| p.deadline).min()
+    }
+
+    /// Finish publishes past their deadline, reporting missing relays as timed out
+    pub fn expire(&mut self, now: Instant) -> Vec<NostrResponse> {
+        let expired: Vec<String> = self.pending
+            .iter()
+            .filter(|(_, pending)| pending.deadline <= now)
+            .map(|(event_id, _)| event_id.clone())
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|event_id| {
+                let pending = self.pending.remove(&event_id)?;
+                Some(pending.into_responses(event_id))
+            })
+            .flatten()
+            .collect()
+    }
+}
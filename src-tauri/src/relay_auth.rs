@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use tracing::{debug, info, warn};
+use crate::nostr_types::{AuthPolicy, NostrResponse};
+
+// NIP-42 client authentication event kind
+pub const AUTH_KIND: u64 = 22242;
+
+#[derive(Default)]
+struct RelayAuthState {
+    challenge: Option<String>,
+    // AuthRequest already sent to the frontend for the current challenge
+    requested: bool,
+    // The frontend declined the current challenge; not asked again until a new
+    // challenge or policy
+    declined: bool,
+    authenticated: bool,
+    // The relay refused something with auth-required:
+    needs_auth: bool,
+    // REQs closed with auth-required:, re-sent after auth
+    closed_subs: Vec<ClosedSub>,
+}
+
+/// A subscription a relay closed with auth-required:, with the filters to
+/// re-send. Group REQs are stored per member.
+pub struct ClosedSub {
+    pub sub_id: String,
+    pub filters: Vec<serde_json::Value>,
+}
+
+/// Outcome of an AUTH attempt: the REQs to re-send if the relay accepted it,
+/// or whose held EOSE to release if not
+pub struct AuthRetry {
+    pub relay: String,
+    pub accepted: bool,
+    pub subs: Vec<ClosedSub>,
+}
+
+/// NIP-42 challenges, policies and pending AUTH replies per relay
+#[derive(Default)]
+pub struct RelayAuth {
+    policies: HashMap<String, AuthPolicy>,
+    relays: HashMap<String, RelayAuthState>,
+    // AUTH event id -> relay it was sent to
+    pending: HashMap<String, String>,
+}
+
+impl RelayAuth {
+    pub fn set_policy(&mut self, relay: &str, policy: AuthPolicy) {
+        if self.policies.insert(relay.to_string(), policy) == Some(policy) {
+            return;
+        }
+        if let Some(state) = self.relays.get_mut(relay) {
+            state.requested = false;
+            state.declined = false;
+        }
+    }
+
+    pub fn policy(&self, relay: &str) -> AuthPolicy {
+        self.policies.get(relay).copied().unwrap_or_default()
+    }
+
+    /// Record an `["AUTH", challenge]` from a relay
+    pub fn challenge(&mut self, relay: &str, challenge: &str) -> Option<NostrResponse> {
+        debug!(relay = %relay, "Received auth challenge");
+        let state = self.relays.entry(relay.to_string()).or_default();
+        state.challenge = Some(challenge.to_string());
+        state.requested = false;
+        state.declined = false;
+        state.authenticated = false;
+        self.request(relay)
+    }
+
+    /// Whether an auth-required: CLOSED from this relay should wait for the
+    /// AUTH result instead of counting as the relay's EOSE
+    pub fn holds_eose(&self, relay: &str) -> bool {
+        self.policy(relay) != AuthPolicy::Never
+            && !self.relays.get(relay).is_some_and(|s| s.authenticated || s.declined)
+    }
+
+    /// A relay closed a REQ with auth-required:
+    pub fn sub_needs_auth(&mut self, relay: &str, subs: Vec<ClosedSub>) -> Option<NostrResponse> {
+        let state = self.relays.entry(relay.to_string()).or_default();
+        state.needs_auth = true;
+        if state.declined {
+            return None;
+        }
+        for sub in subs {
+            state.closed_subs.retain(|s| s.sub_id != sub.sub_id);
+            state.closed_subs.push(sub);
+        }
+        self.request(relay)
+    }
+
+    /// A relay rejected an EVENT with auth-required:. The event stays in the
+    /// outbox and is re-sent after auth.
+    pub fn event_needs_auth(&mut self, relay: &str) -> Option<NostrResponse> {
+        self.relays.entry(relay.to_string()).or_default().needs_auth = true;
+        self.request(relay)
+    }
+
+    /// Ask the frontend to sign an AUTH event if the relay's policy allows it:
+    /// `always` as soon as a challenge arrives, `ask` once the relay actually
+    /// refused something, `never` not at all.
+    fn request(&mut self, relay: &str) -> Option<NostrResponse> {
+        let policy = self.policy(relay);
+        let state = self.relays.get_mut(relay)?;
+        let challenge = state.challenge.clone()?;
+        if state.requested || state.declined || state.authenticated {
+            return None;
+        }
+        let wanted = match policy {
+            AuthPolicy::Always => true,
+            AuthPolicy::Ask => state.needs_auth,
+            AuthPolicy::Never => false,
+        };
+        if !wanted {
+            return None;
+        }
+        state.requested = true;
+        Some(NostrResponse::AuthRequest {
+            relay: relay.to_string(),
+            challenge,
+            ask: policy == AuthPolicy::Ask,
+        })
+    }
+
+    /// Check a signed AUTH event from the frontend against the relay's challenge.
+    /// Returns the event id to match the relay's OK against.
+    pub fn prepare_response(&mut self, relay: &str, event: &serde_json::Value) -> Result<String, String> {
+        let challenge = self.relays
+            .get(relay)
+            .and_then(|s| s.challenge.as_deref())
+            .ok_or_else(|| format!("No auth challenge from {}", relay))?;
+
+        if event.get("kind").and_then(|k| k.as_u64()) != Some(AUTH_KIND) {
+            return Err(format!("AUTH event must be kind {}", AUTH_KIND));
+        }
+        let signed_challenge = event
+            .get("tags")
+            .and_then(|t| t.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|tag| tag.as_array())
+            .find(|tag| tag.first().and_then(|v| v.as_str()) == Some("challenge"))
+            .and_then(|tag| tag.get(1))
+            .and_then(|v| v.as_str());
+        if signed_challenge != Some(challenge) {
+            return Err(format!("AUTH event does not answer the current challenge from {}", relay));
+        }
+
+        let event_id = event
+            .get("id")
+            .and_then(|v| v.as_str())
+            .ok_or("AUTH event has no id")?
+            .to_string();
+        self.pending.insert(event_id.clone(), relay.to_string());
+        Ok(event_id)
+    }
+
+    /// The frontend won't answer the relay's AuthRequest. It isn't asked again
+    /// until the relay sends a new challenge or its policy changes; the REQs
+    /// closed so far stop waiting for auth.
+    pub fn decline(&mut self, relay: &str) -> Option<AuthRetry> {
+        let state = self.relays.get_mut(relay)?;
+        debug!(relay = %relay, "Auth declined");
+        state.requested = false;
+        state.declined = true;
+        Some(AuthRetry {
+            relay: relay.to_string(),
+            accepted: false,
+            subs: std::mem::take(&mut state.closed_subs),
+        })
+    }
+
+    pub fn is_pending(&self, event_id: &str) -> bool {
+        self.pending.contains_key(event_id)
+    }
+
+    /// Handle the relay's OK for our AUTH event
+    pub fn auth_ok(&mut self, event_id: &str, accepted: bool, message: &str) -> Option<AuthRetry> {
+        let relay = self.pending.remove(event_id)?;
+        let state = self.relays.entry(relay.clone()).or_default();
+        if accepted {
+            info!(relay = %relay, "Authenticated with relay");
+            state.authenticated = true;
+            state.needs_auth = false;
+        } else {
+            warn!(relay = %relay, reason = %message, "Relay rejected AUTH");
+        }
+        Some(AuthRetry {
+            relay,
+            accepted,
+            subs: std::mem::take(&mut state.closed_subs),
+        })
+    }
+
+    /// Forget challenge and auth state when a relay disconnects - it will send a new challenge
+    pub fn relay_gone(&mut self, relay: &str) {
+        self.relays.remove(relay);
+        self.pending.retain(|_, r| r != relay);
+    }
+}
@@ -1,25 +1,25 @@
-use enostr::RelayPool;
+use enostr::{RelayPool, ClientMessage};
 use tauri::Emitter;
 use tracing::{info, error};
+use crate::outbox::Outbox;
+use crate::wakeup::Wakeup;
+use crate::nostr_types::NostrResponse;
 
-pub fn handle_add_relay(pool: &mut RelayPool, url: String, app_handle: &tauri::AppHandle) {
+pub fn handle_add_relay(pool: &mut RelayPool, url: String, wakeup: &Wakeup, app_handle: &tauri::AppHandle) {
     info!(relay = %url, "Adding relay");
-    let wakeup = || {};
-    match pool.add_url(url.clone(), wakeup) {
+    match pool.add_url(url.clone(), wakeup.callback()) {
         Ok(_) => {
             info!(relay = %url, "Relay added");
-            let _ = app_handle.emit("nostr_event", serde_json::json!({
-                "type": "relayAdded",
-                "url": url
-            }));
+            let _ = app_handle.emit("nostr_event", NostrResponse::RelayAdded { relay: url });
         }
         Err(e) => error!(relay = %url, error = ?e, "Failed to add relay"),
     }
 }
 
-pub fn handle_remove_relay(pool: &mut RelayPool, url: String) {
+pub fn handle_remove_relay(pool: &mut RelayPool, url: String, app_handle: &tauri::AppHandle) {
     pool.relays.retain(|r| r.url() != url);
     info!(relay = %url, "Relay removed");
+    let _ = app_handle.emit("nostr_event", NostrResponse::RelayRemoved { relay: url });
 }
 
 pub fn handle_connect_relay(pool: &mut RelayPool, url: String) {
@@ -52,3 +52,51 @@ pub fn handle_reconnect_disconnected(pool: &mut RelayPool, reason: Option<String
     }
     info!(count = reconnected, reason = ?reason, "Reconnecting disconnected relays");
 }
+
+/// URLs of all websocket relays in the pool, connected or not
+pub fn websocket_relay_urls(pool: &RelayPool) -> Vec<String> {
+    pool.relays
+        .iter()
+        .filter(|relay| matches!(relay, enostr::PoolRelay::Websocket(_)))
+        .map(|relay| relay.url().to_string())
+        .collect()
+}
+
+/// Send queued outbox events that are due to a relay
+pub fn flush_outbox(pool: &mut RelayPool, outbox: &mut Outbox, relay_url: &str) {
+    for event_json in outbox.take_due(relay_url) {
+        match ClientMessage::event_json(event_json) {
+            Ok(msg) => pool.send_to(&msg, relay_url),
+            Err(e) => error!(relay = %relay_url, error = ?e, "Invalid outbox event"),
+        }
+    }
+}
+
+/// Relays to wait on for EOSE: websocket relays that are connected or still
+/// connecting (messages are queued until the socket opens). Relays that drop
+/// later are released by EoseTracker::relay_gone, slow ones by the timeout.
+/// If every relay is disconnected, wait on all of them: they may reconnect
+/// before the timeout, and an empty list would mean EOSE right away.
+pub fn eose_relay_urls(pool: &RelayPool) -> Vec<String> {
+    let reachable: Vec<String> = pool.relays
+        .iter()
+        .filter(|relay| matches!(relay, enostr::PoolRelay::Websocket(_)))
+        .filter(|relay| !matches!(relay.status(), enostr::RelayStatus::Disconnected))
+        .map(|relay| relay.url().to_string())
+        .collect();
+    if reachable.is_empty() {
+        websocket_relay_urls(pool)
+    } else {
+        reachable
+    }
+}
+
+/// URLs of connected websocket relays (multicast never sends EOSE/OK)
+pub fn connected_relay_urls(pool: &RelayPool) -> Vec<String> {
+    pool.relays
+        .iter()
+        .filter(|relay| matches!(relay, enostr::PoolRelay::Websocket(_)))
+        .filter(|relay| matches!(relay.status(), enostr::RelayStatus::Connected))
+        .map(|relay| relay.url().to_string())
+        .collect()
+}
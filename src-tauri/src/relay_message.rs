@@ -0,0 +1,162 @@
+use std::borrow::Cow;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+
+// A JSON string, borrowed from the frame unless it contains escapes
+#[derive(Deserialize)]
+struct Text<'a>(#[serde(borrow)] Cow<'a, str>);
+
+// Only the top-level `id` of an event; other fields (tags included) are skipped
+#[derive(Deserialize)]
+struct EventId<'a> {
+    #[serde(borrow)]
+    id: Cow<'a, str>,
+}
+
+// Only the top-level `sig` of an event
+#[derive(Deserialize)]
+struct EventSig<'a> {
+    #[serde(borrow)]
+    sig: Cow<'a, str>,
+}
+
+#[derive(Deserialize)]
+struct CountPayload {
+    #[serde(default)]
+    count: u64,
+    #[serde(default)]
+    approximate: bool,
+}
+
+/// A relay-to-client message, classified without building a `serde_json::Value`.
+/// Strings and the event object borrow from the received frame.
+pub enum RelayMessage<'a> {
+    Event { sub_id: Cow<'a, str>, event: &'a RawValue },
+    Eose { sub_id: Cow<'a, str> },
+    Ok { event_id: Cow<'a, str>, accepted: bool, message: Cow<'a, str> },
+    Notice { message: Cow<'a, str> },
+    Closed { sub_id: Cow<'a, str>, reason: Cow<'a, str> },
+    Auth { challenge: Cow<'a, str> },
+    Count { query_id: Cow<'a, str>, count: u64, approximate: bool },
+}
+
+impl<'a> RelayMessage<'a> {
+    /// Classify a text frame. None for malformed or unknown messages.
+    pub fn parse(text: &'a str) -> Option<Self> {
+        let parts: Vec<&'a RawValue> = serde_json::from_str(text).ok()?;
+        let Text(kind) = serde_json::from_str(parts.first()?.get()).ok()?;
+
+        let text_at = |index: usize| -> Option<Cow<'a, str>> {
+            let Text(value) = serde_json::from_str(parts.get(index)?.get()).ok()?;
+            Some(value)
+        };
+
+        let message = match kind.as_ref() {
+            "EVENT" => RelayMessage::Event {
+                sub_id: text_at(1)?,
+                event: parts.get(2)?,
+            },
+            "EOSE" => RelayMessage::Eose { sub_id: text_at(1)? },
+            "OK" => RelayMessage::Ok {
+                event_id: text_at(1)?,
+                accepted: serde_json::from_str(parts.get(2)?.get()).unwrap_or(false),
+                message: text_at(3).unwrap_or_default(),
+            },
+            "NOTICE" => RelayMessage::Notice { message: text_at(1)? },
+            "CLOSED" => RelayMessage::Closed {
+                sub_id: text_at(1)?,
+                reason: text_at(2).unwrap_or_default(),
+            },
+            "AUTH" => RelayMessage::Auth { challenge: text_at(1)? },
+            "COUNT" => {
+                let payload: CountPayload = serde_json::from_str(parts.get(2)?.get()).ok()?;
+                RelayMessage::Count {
+                    query_id: text_at(1)?,
+                    count: payload.count,
+                    approximate: payload.approximate,
+                }
+            }
+            _ => return None,
+        };
+        Some(message)
+    }
+}
+
+/// The id of an EVENT message's event object, independent of key order and whitespace
+pub fn event_id(event: &RawValue) -> Option<[u8; 32]> {
+    let EventId { id } = serde_json::from_str(event.get()).ok()?;
+    let mut bytes = [0u8; 32];
+    hex::decode_to_slice(id.as_ref(), &mut bytes).ok()?;
+    Some(bytes)
+}
+
+/// The signature of an EVENT message's event object
+pub fn event_sig(event: &RawValue) -> Option<[u8; 64]> {
+    let EventSig { sig } = serde_json::from_str(event.get()).ok()?;
+    let mut bytes = [0u8; 64];
+    hex::decode_to_slice(sig.as_ref(), &mut bytes).ok()?;
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "c50fc8c054b2b42d580574e8295174dbf32a1124fa53fe5003a95b933f9df659";
+
+    #[test]
+    fn parses_event_with_whitespace() {
+        let text = format!(r#"[ "EVENT" , "sub1" ,
+            {{"kind": 1, "id": "{}", "content": ""}} ]"#, ID);
+        let Some(RelayMessage::Event { sub_id, event }) = RelayMessage::parse(&text) else {
+            panic!("not an EVENT");
+        };
+        assert_eq!(sub_id, "sub1");
+        assert_eq!(event_id(event).map(hex::encode).as_deref(), Some(ID));
+    }
+
+    #[test]
+    fn event_id_ignores_id_inside_tags() {
+        let other = "00".repeat(32);
+        let text = format!(
+            r#"["EVENT","sub1",{{"tags":[["x","\"id\":\"{}"]],"content":"\"id\":\"{}","id":"{}"}}]"#,
+            other, other, ID,
+        );
+        let Some(RelayMessage::Event { event, .. }) = RelayMessage::parse(&text) else {
+            panic!("not an EVENT");
+        };
+        assert_eq!(event_id(event).map(hex::encode).as_deref(), Some(ID));
+    }
+
+    #[test]
+    fn event_sig_reads_top_level_sig() {
+        let sig = "ab".repeat(64);
+        let text = format!(r#"["EVENT","sub1",{{"id":"{}","sig":"{}"}}]"#, ID, sig);
+        let Some(RelayMessage::Event { event, .. }) = RelayMessage::parse(&text) else {
+            panic!("not an EVENT");
+        };
+        assert_eq!(event_sig(event).map(hex::encode), Some(sig));
+
+        let text = format!(r#"["EVENT","sub1",{{"id":"{}"}}]"#, ID);
+        let Some(RelayMessage::Event { event, .. }) = RelayMessage::parse(&text) else {
+            panic!("not an EVENT");
+        };
+        assert_eq!(event_sig(event), None);
+    }
+
+    #[test]
+    fn unescapes_sub_ids() {
+        let Some(RelayMessage::Eose { sub_id }) = RelayMessage::parse(r#"["EOSE","a\"bé"]"#) else {
+            panic!("not an EOSE");
+        };
+        assert_eq!(sub_id, "a\"bé");
+
+        let Some(RelayMessage::Closed { sub_id, reason }) =
+            RelayMessage::parse(r#"["CLOSED","sub\\1","auth-required: members only"]"#)
+        else {
+            panic!("not a CLOSED");
+        };
+        assert_eq!(sub_id, "sub\\1");
+        assert_eq!(reason, "auth-required: members only");
+    }
+}
@@ -0,0 +1,31 @@
+use std::collections::HashSet;
+use tracing::debug;
+
+/// NIP-50 search: which relays accept `search` filters
+#[derive(Default)]
+pub struct SearchState {
+    relays: HashSet<String>,
+}
+
+impl SearchState {
+    pub fn set_relays(&mut self, urls: Vec<String>) {
+        debug!(count = urls.len(), "Search relays updated");
+        self.relays = urls.iter().map(|url| normalize(url).to_string()).collect();
+    }
+
+    pub fn is_search_relay(&self, url: &str) -> bool {
+        self.relays.contains(normalize(url))
+    }
+}
+
+// Pool and settings URLs may differ by a trailing slash
+fn normalize(url: &str) -> &str {
+    url.trim_end_matches('/')
+}
+
+/// True if any of the JSON filters has a NIP-50 `search` term
+pub fn has_search(filters: &[serde_json::Value]) -> bool {
+    filters
+        .iter()
+        .any(|f| f.get("search").and_then(|s| s.as_str()).is_some_and(|s| !s.is_empty()))
+}
@@ -0,0 +1,93 @@
+use std::collections::{HashMap, VecDeque};
+
+// Event ids remembered before the oldest is forgotten
+pub const SEEN_CAPACITY: usize = 50_000;
+
+struct SeenEntry {
+    // Indexes into SeenEvents::relay_urls, in delivery order
+    relays: Vec<u16>,
+    // Signature of the note nostrdb stored; later copies with it need no processing
+    stored_sig: Option<[u8; 64]>,
+}
+
+/// Recently received event ids, checked before nostrdb so duplicates from
+/// several relays are dropped without a transaction. Also records which relays
+/// delivered each id ("seen on").
+pub struct SeenEvents {
+    entries: HashMap<[u8; 32], SeenEntry>,
+    // Insertion order, for evicting the oldest id
+    order: VecDeque<[u8; 32]>,
+    capacity: usize,
+    relay_urls: Vec<String>,
+    relay_index: HashMap<String, u16>,
+}
+
+impl Default for SeenEvents {
+    fn default() -> Self {
+        Self::new(SEEN_CAPACITY)
+    }
+}
+
+impl SeenEvents {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+            relay_urls: Vec::new(),
+            relay_index: HashMap::new(),
+        }
+    }
+
+    /// Record that a relay delivered a valid copy of an event: one nostrdb
+    /// accepted, or one with the stored note's signature
+    pub fn record(&mut self, event_id: &[u8; 32], relay_url: &str) {
+        let relay = self.relay(relay_url);
+        let entry = self.entry(event_id);
+        if !entry.relays.contains(&relay) {
+            entry.relays.push(relay);
+        }
+    }
+
+    /// nostrdb has the event with this signature; later deliveries skip processing
+    pub fn mark_stored(&mut self, event_id: &[u8; 32], sig: [u8; 64]) {
+        self.entry(event_id).stored_sig = Some(sig);
+    }
+
+    /// Signature of the stored note, if nostrdb is known to have the event
+    pub fn stored_sig(&self, event_id: &[u8; 32]) -> Option<[u8; 64]> {
+        self.entries.get(event_id).and_then(|entry| entry.stored_sig)
+    }
+
+    /// Relays that delivered the event, if it is still remembered
+    pub fn relays(&self, event_id: &[u8; 32]) -> Vec<String> {
+        self.entries
+            .get(event_id)
+            .map(|entry| entry.relays.iter().map(|&i| self.relay_urls[i as usize].clone()).collect())
+            .unwrap_or_default()
+    }
+
+    fn entry(&mut self, event_id: &[u8; 32]) -> &mut SeenEntry {
+        if !self.entries.contains_key(event_id) {
+            if self.order.len() >= self.capacity {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.entries.remove(&oldest);
+                }
+            }
+            self.order.push_back(*event_id);
+        }
+        self.entries
+            .entry(*event_id)
+            .or_insert_with(|| SeenEntry { relays: Vec::new(), stored_sig: None })
+    }
+
+    fn relay(&mut self, relay_url: &str) -> u16 {
+        if let Some(&index) = self.relay_index.get(relay_url) {
+            return index;
+        }
+        let index = self.relay_urls.len() as u16;
+        self.relay_urls.push(relay_url.to_string());
+        self.relay_index.insert(relay_url.to_string(), index);
+        index
+    }
+}
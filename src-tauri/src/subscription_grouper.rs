@@ -0,0 +1,182 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::time::{Duration, Instant};
+use nostrdb::{Filter, FilterField, Note};
+use tracing::debug;
+
+// Batching window for groupable subscriptions (matches NDK's groupableDelay)
+pub const GROUP_WINDOW: Duration = Duration::from_millis(100);
+
+const GROUP_PREFIX: &str = "grp-";
+
+/// A groupable subscription waiting for its batch to be sent
+pub struct GroupMember {
+    pub sub_id: String,
+    pub authors: Vec<[u8; 32]>,
+    pub eose_timeout: Duration,
+    pub close_on_eose: bool,
+}
+
+struct PendingGroup {
+    kinds: Vec<u64>,
+    members: Vec<GroupMember>,
+    flush_at: Instant,
+}
+
+/// A merged REQ ready to be sent to relays
+pub struct FlushedGroup {
+    pub group_id: String,
+    pub filter: Filter,
+    pub members: Vec<GroupMember>,
+}
+
+/// Batches compatible groupable subscriptions into shared relay REQs
+#[derive(Default)]
+pub struct SubscriptionGrouper {
+    pending: HashMap<BTreeSet<u64>, PendingGroup>,
+    groups: HashMap<String, Vec<String>>,
+    member_group: HashMap<String, String>,
+    // Each member's own kinds and authors, to tell which members a group event is for
+    member_filters: HashMap<String, (BTreeSet<u64>, HashSet<[u8; 32]>)>,
+    next_group: u64,
+}
+
+impl SubscriptionGrouper {
+    /// Queue a subscription for grouping. Returns false if its relay filters
+    /// can't be merged (anything other than a single same-kind author list).
+    pub fn add(
+        &mut self,
+        sub_id: &str,
+        relay_filters: &[Filter],
+        eose_timeout: Duration,
+        close_on_eose: bool,
+    ) -> bool {
+        let [filter] = relay_filters else {
+            return false;
+        };
+        let Some((kinds, authors)) = author_list(filter) else {
+            return false;
+        };
+
+        let key: BTreeSet<u64> = kinds.iter().copied().collect();
+        self.member_filters.insert(sub_id.to_string(), (key.clone(), authors.iter().copied().collect()));
+        let pending = self.pending.entry(key).or_insert_with(|| PendingGroup {
+            kinds,
+            members: Vec::new(),
+            flush_at: Instant::now() + GROUP_WINDOW,
+        });
+        pending.members.push(GroupMember {
+            sub_id: sub_id.to_string(),
+            authors,
+            eose_timeout,
+            close_on_eose,
+        });
+        debug!(sub_id = %sub_id, batch_size = pending.members.len(), "Queued groupable subscription");
+        true
+    }
+
+    /// When the next pending batch should be sent
+    pub fn next_flush(&self) -> Option<Instant> {
+        self.pending.values().map(|p| p.flush_at).min()
+    }
+
+    /// Take batches whose window has passed, merged into one filter each
+    pub fn flush_due(&mut self, now: Instant) -> Vec<FlushedGroup> {
+        let due: Vec<BTreeSet<u64>> = self.pending
+            .iter()
+            .filter(|(_, pending)| pending.flush_at <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        let mut flushed = Vec::new();
+        for key in due {
+            let Some(pending) = self.pending.remove(&key) else {
+                continue;
+            };
+
+            let mut authors: Vec<[u8; 32]> = pending.members
+                .iter()
+                .flat_map(|m| m.authors.iter().copied())
+                .collect();
+            authors.sort_unstable();
+            authors.dedup();
+
+            let filter = Filter::new()
+                .kinds(pending.kinds)
+                .authors(authors.iter())
+                .build();
+
+            self.next_group += 1;
+            let group_id = format!("{}{}", GROUP_PREFIX, self.next_group);
+            let member_ids: Vec<String> = pending.members.iter().map(|m| m.sub_id.clone()).collect();
+            for member_id in &member_ids {
+                self.member_group.insert(member_id.clone(), group_id.clone());
+            }
+            debug!(group_id = %group_id, members = member_ids.len(), authors = authors.len(), "Flushing subscription group");
+            self.groups.insert(group_id.clone(), member_ids);
+
+            flushed.push(FlushedGroup {
+                group_id,
+                filter,
+                members: pending.members,
+            });
+        }
+        flushed
+    }
+
+    pub fn is_group(sub_id: &str) -> bool {
+        sub_id.starts_with(GROUP_PREFIX)
+    }
+
+    pub fn members(&self, group_id: &str) -> Option<&Vec<String>> {
+        self.groups.get(group_id)
+    }
+
+    /// True if the subscription is batched or grouped, so it has no REQ of its own
+    pub fn is_member(&self, sub_id: &str) -> bool {
+        self.member_group.contains_key(sub_id)
+            || self.pending.values().any(|pending| pending.members.iter().any(|m| m.sub_id == sub_id))
+    }
+
+    /// True if the note matches the member's own filter, not just its group's merged one
+    pub fn member_matches(&self, sub_id: &str, note: &Note) -> bool {
+        self.member_filters.get(sub_id).is_some_and(|(kinds, authors)| {
+            kinds.contains(&u64::from(note.kind())) && authors.contains(note.pubkey())
+        })
+    }
+
+    /// Remove a subscription from its batch or group.
+    /// Returns the group id if the group is now empty and its REQ should be closed.
+    pub fn remove_member(&mut self, sub_id: &str) -> Option<String> {
+        self.member_filters.remove(sub_id);
+        for pending in self.pending.values_mut() {
+            pending.members.retain(|m| m.sub_id != sub_id);
+        }
+        self.pending.retain(|_, pending| !pending.members.is_empty());
+
+        let group_id = self.member_group.remove(sub_id)?;
+        let members = self.groups.get_mut(&group_id)?;
+        members.retain(|m| m != sub_id);
+        if members.is_empty() {
+            self.groups.remove(&group_id);
+            return Some(group_id);
+        }
+        None
+    }
+}
+
+/// Extract (kinds, authors) if the filter is a plain same-kind author list
+fn author_list(filter: &Filter) -> Option<(Vec<u64>, Vec<[u8; 32]>)> {
+    let mut kinds = Vec::new();
+    let mut authors = Vec::new();
+    for field in filter.into_iter() {
+        match field {
+            FilterField::Kinds(k) => kinds.extend(k.into_iter()),
+            FilterField::Authors(a) => authors.extend(a.into_iter().copied()),
+            _ => return None,
+        }
+    }
+    if kinds.is_empty() || authors.is_empty() {
+        return None;
+    }
+    Some((kinds, authors))
+}
@@ -1,34 +1,83 @@
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use nostrdb::{Ndb, Filter, Subscription, Transaction};
 use enostr::{RelayPool, ClientMessage};
 use tauri::Emitter;
+use tauri::ipc::Channel;
+use serde_json::value::RawValue;
 use tracing::{debug, info, warn, error};
-use crate::nostr_types::{NostrResponse, SubscribeOpts, PublishOpts};
-use crate::filter_parser::parse_filter;
+use crate::nostr_types::{CachedEvents, NostrResponse, SubscribeOpts, PublishOpts};
+use crate::filter_parser::{filter_limit, has_relay_only_tags, parse_filters};
+use crate::eose_tracker::{EoseTracker, DEFAULT_EOSE_TIMEOUT};
+use crate::relay_handlers::{connected_relay_urls, eose_relay_urls, websocket_relay_urls};
+use crate::outbox::Outbox;
+use crate::event_verify::{verify_event, InvalidEventCounter};
+use crate::subscription_grouper::SubscriptionGrouper;
+use crate::publish_tracker::PublishTracker;
+use crate::count_tracker::CountTracker;
+use crate::search::{has_search, SearchState};
+use crate::direct_subs::DirectSubscriptions;
+use crate::delivered::DeliveredEvents;
+
+// Max notes drained from a single nostrdb subscription per poll
+const MAX_NOTES_PER_POLL: u32 = 256;
+// Cache query size when neither the filters nor the subscription set one
+const DEFAULT_QUERY_LIMIT: u64 = 1000;
+// Upper bound on any single cache query
+const MAX_QUERY_LIMIT: u64 = 10_000;
+// Cached events per channel message
+const CACHED_BATCH: usize = 200;
 
 pub fn handle_subscribe(
     id: String,
     filters: Vec<serde_json::Value>,
     subscribe_opts: Option<SubscribeOpts>,
-    ndb: &Ndb,
+    ndb: &mut Ndb,
     pool: &mut RelayPool,
     subscriptions: &mut HashMap<String, Subscription>,
     sub_id_map: &mut HashMap<u64, String>,
+    eose_tracker: &mut EoseTracker,
+    grouper: &mut SubscriptionGrouper,
+    search: &SearchState,
+    direct: &mut DirectSubscriptions,
+    delivered: &mut DeliveredEvents,
+    cached_channel: Option<&Channel<CachedEvents>>,
     _app_handle: &tauri::AppHandle,
 ) {
     info!(sub_id = %id, filter_count = filters.len(), "Subscribe request");
 
-    // Parse JSON filters
-    let parsed_filters: Vec<Filter> = filters
-        .iter()
-        .filter_map(|f| parse_filter(f))
-        .collect();
+    // Parse JSON filters. A malformed filter is reported rather than loosened into a broader query.
+    let parsed = match parse_filters(&filters) {
+        Ok(parsed) if !parsed.is_empty() => parsed,
+        result => {
+            let error = result.err().unwrap_or_else(|| "no filters".to_string());
+            warn!(sub_id = %id, error = %error, "Invalid subscription filters");
+            let _ = _app_handle.emit("nostr_event", NostrResponse::Error {
+                id: Some(id.clone()),
+                error,
+            });
+            // Still end the subscription so fetches waiting on EOSE resolve
+            let _ = _app_handle.emit("nostr_event", NostrResponse::Eose { sub_id: id });
+            return;
+        }
+    };
 
-    if parsed_filters.is_empty() {
-        warn!(sub_id = %id, "No valid filters");
-        return;
+    // Filters with tags nostrdb can't index only go to relays, as their original JSON
+    let mut parsed_filters = Vec::new();
+    let mut relay_only = Vec::new();
+    for (json, filter) in filters.iter().zip(parsed) {
+        if has_relay_only_tags(json) {
+            relay_only.push(json.clone());
+        } else {
+            parsed_filters.push(filter);
+        }
     }
 
+    let close_on_eose = subscribe_opts
+        .as_ref()
+        .and_then(|opts| opts.close_on_eose)
+        .unwrap_or(false);
+
     // Check if cache-only
     let cache_only = subscribe_opts
         .as_ref()
@@ -36,14 +85,16 @@ pub fn handle_subscribe(
         .map(|dests| dests.contains(&"cache".to_string()) && !dests.contains(&"relay".to_string()))
         .unwrap_or(false);
 
-    let filter = parsed_filters[0].clone();
-
-    // Check if this is an ID-based query (check without consuming iterator)
-    let is_id_query = filter.clone().into_iter().any(|field| matches!(field, nostrdb::FilterField::Ids(_)));
-
-    // Subscribe in nostrdb (skip for ID queries - we handle them via direct lookup)
-    if !is_id_query {
-        match ndb.subscribe(&[filter.clone()]) {
+    // nostrdb subscriptions can't match search terms or relay-only tags, so these
+    // get the local query results plus relay hits, merged by DeliveredEvents
+    let is_search = has_search(&filters);
+    let is_direct = is_search || !relay_only.is_empty();
+    delivered.start(&id);
+    if is_direct {
+        direct.start(&id);
+    } else {
+        // Subscribe in nostrdb over the whole filter set so later matches get polled
+        match ndb.subscribe(&parsed_filters) {
             Ok(sub) => {
                 let ndb_id = sub.id();
                 subscriptions.insert(id.clone(), sub);
@@ -54,37 +105,33 @@ pub fn handle_subscribe(
                 error!(sub_id = %id, error = ?e, active_subs = subscriptions.len(), "Nostrdb subscribe failed");
             }
         }
-    } else {
-        info!(sub_id = %id, "Skipping nostrdb subscription for ID query");
     }
 
-    // Query cache and determine what to fetch from relays (single cache pass)
-    let mut relay_filters = parsed_filters.clone();
-    let mut skip_relay_req = cache_only;
+    // Split pure ID lookups (served by direct key lookup) from everything else
+    let (id_filters, query_filters): (Vec<Filter>, Vec<Filter>) = parsed_filters
+        .into_iter()
+        .partition(is_id_lookup);
 
-    if let Ok(txn) = Transaction::new(ndb) {
-        // Check if this is an ID-based query
-        let has_ids = filter.into_iter().any(|field| matches!(field, nostrdb::FilterField::Ids(_)));
+    // Query cache and determine what to fetch from relays (single cache pass).
+    // Cached events are kept as nostrdb's JSON and sent after the query.
+    let mut relay_filters = query_filters.clone();
+    let mut cached = Vec::new();
 
-        if has_ids {
-            // Fast path: direct ID lookup, emit found events, track unfound IDs for relay REQ
-            let mut emitted = 0;
-            let mut unfound_ids = Vec::new();
+    if let Ok(txn) = Transaction::new(ndb) {
+        // Fast path: direct ID lookup, collect found events, track unfound IDs for relay REQ
+        let mut unfound_ids = Vec::new();
 
+        for filter in id_filters.iter() {
             for field in filter.into_iter() {
                 if let nostrdb::FilterField::Ids(ids) = field {
                     for id_bytes in ids.into_iter() {
                         if let Ok(note_key) = ndb.get_notekey_by_id(&txn, id_bytes) {
                             if let Ok(note) = ndb.get_note_by_key(&txn, note_key) {
-                                if let Ok(event_json) = note.json() {
-                                    if let Ok(event) = serde_json::from_str::<serde_json::Value>(&event_json) {
-                                        let _ = _app_handle.emit("nostr_event", NostrResponse::Event {
-                                            sub_id: id.clone(),
-                                            event,
-                                            relay: None,
-                                        });
-                                        emitted += 1;
-                                    }
+                                if !delivered.first_delivery(&id, note.id()) {
+                                    continue;
+                                }
+                                if let Some(event) = note_event(&note) {
+                                    cached.push(event);
                                 }
                             }
                         } else {
@@ -93,58 +140,429 @@ pub fn handle_subscribe(
                     }
                 }
             }
+        }
 
+        // Only request unfound IDs from relays
+        if !unfound_ids.is_empty() {
+            relay_filters.push(nostrdb::Filter::new().ids(unfound_ids.iter()).build());
+        }
 
-            // Skip relay REQ if all IDs found
-            if unfound_ids.is_empty() {
-                skip_relay_req = true;
-            } else if emitted > 0 {
-                // Update relay filters to only request unfound IDs
-                relay_filters = vec![nostrdb::Filter::new().ids(unfound_ids.iter()).build()];
-            }
-        } else {
-            // Slow path: full query for non-ID filters
-            let mut emitted = 0;
-            if let Ok(results) = ndb.query(&txn, &[filter.clone()], 1000) {
+        // Slow path: full query for the remaining filters
+        if !query_filters.is_empty() {
+            let cache_limit = subscribe_opts.as_ref().and_then(|opts| opts.cache_limit);
+            let limit = query_limit(&query_filters, cache_limit);
+            if let Ok(results) = ndb.query(&txn, &query_filters, limit as i32) {
+                if results.len() >= limit as usize {
+                    let oldest = results.iter().map(|r| r.note.created_at()).min();
+                    info!(sub_id = %id, limit = limit, oldest = ?oldest, "Cache query hit its limit");
+                    let _ = _app_handle.emit("nostr_event", NostrResponse::CacheLimitReached {
+                        sub_id: id.clone(),
+                        limit,
+                        oldest,
+                    });
+                }
                 for result in results.iter() {
-                    if let Ok(event_json) = result.note.json() {
-                        if let Ok(event) = serde_json::from_str::<serde_json::Value>(&event_json) {
-                            let _ = _app_handle.emit("nostr_event", NostrResponse::Event {
-                                sub_id: id.clone(),
-                                event,
-                                relay: None,
-                            });
-                            emitted += 1;
-                        }
+                    if !delivered.first_delivery(&id, result.note.id()) {
+                        continue;
+                    }
+                    if let Some(event) = note_event(&result.note) {
+                        cached.push(event);
                     }
                 }
             }
-            if emitted > 0 {
-                debug!(sub_id = %id, count = emitted, "Emitted cached events");
-            }
         }
+
+    } else {
+        relay_filters.extend(id_filters);
     }
 
-    // Skip relay REQ if all events found in cache
-    if skip_relay_req {
-        debug!(sub_id = %id, "All events in cache - skipping relay REQ and EOSE");
+    // Skip relay REQ if cache-only or all events found in cache
+    let cache_done = cache_only || (relay_filters.is_empty() && relay_only.is_empty());
+    let eose_sent = emit_cached(&id, cached, cache_done, cached_channel, _app_handle);
+
+    if cache_done {
+        debug!(sub_id = %id, "All events in cache - skipping relay REQ");
+        if !eose_sent {
+            let _ = _app_handle.emit("nostr_event", NostrResponse::Eose { sub_id: id.clone() });
+        }
+        if close_on_eose {
+            close_subscription(&id, ndb, pool, subscriptions, sub_id_map, grouper, direct);
+        }
+        return;
+    }
+
+    let eose_timeout = subscribe_opts
+        .as_ref()
+        .and_then(|opts| opts.eose_timeout_ms)
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_EOSE_TIMEOUT);
+
+    // Direct subscriptions send the original JSON filters, since search terms and
+    // relay-only tags don't survive a nostrdb Filter. Only search-capable relays
+    // understand NIP-50 filters.
+    if is_direct {
+        let relays: Vec<String> = eose_relay_urls(pool)
+            .into_iter()
+            .filter(|relay| !is_search || search.is_search_relay(relay))
+            .collect();
+        let msg = raw_message("REQ", &id, &filters);
+        for relay in &relays {
+            pool.send_to(&msg, relay);
+        }
+        info!(sub_id = %id, relay_count = relays.len(), search = is_search, "Sent direct REQ to relays");
+        if !eose_tracker.start(&id, relays, eose_timeout, close_on_eose) {
+            finish_eose(id, ndb, pool, subscriptions, sub_id_map, eose_tracker, grouper, direct, _app_handle);
+        }
+        return;
+    }
+
+    // Groupable subscriptions wait for a shared REQ (sent by flush_groups)
+    let groupable = subscribe_opts
+        .as_ref()
+        .and_then(|opts| opts.groupable)
+        .unwrap_or(false);
+    if groupable && grouper.add(&id, &relay_filters, eose_timeout, close_on_eose) {
         return;
     }
 
     // Use pool.subscribe() which handles negentropy for eligible filters
     pool.subscribe(id.clone(), relay_filters);
     info!(sub_id = %id, relay_count = pool.relays.len(), "Subscribed to relays (with negentropy if eligible)");
+
+    if !eose_tracker.start(&id, eose_relay_urls(pool), eose_timeout, close_on_eose) {
+        finish_eose(id, ndb, pool, subscriptions, sub_id_map, eose_tracker, grouper, direct, _app_handle);
+    }
+}
+
+/// Deliver cached events for a new subscription. With a channel they go out in
+/// batches of raw nostrdb JSON, and the last batch carries EOSE when the cache
+/// answered the whole subscription, so the frontend sees it after the events.
+/// Without one (checkpoint restore) each event is emitted on its own.
+/// Returns true if EOSE was delivered with the events.
+fn emit_cached(
+    sub_id: &str,
+    cached: Vec<Box<RawValue>>,
+    eose: bool,
+    channel: Option<&Channel<CachedEvents>>,
+    app_handle: &tauri::AppHandle,
+) -> bool {
+    if cached.is_empty() {
+        return false;
+    }
+    debug!(sub_id = %sub_id, count = cached.len(), batched = channel.is_some(), "Emitting cached events");
+
+    let Some(channel) = channel else {
+        for event in cached {
+            let _ = app_handle.emit("nostr_event", NostrResponse::Event {
+                sub_id: sub_id.to_string(),
+                event,
+                relay: None,
+            });
+        }
+        return false;
+    };
+
+    let mut events = cached.into_iter().peekable();
+    while events.peek().is_some() {
+        let batch_events = events.by_ref().take(CACHED_BATCH).collect();
+        let batch = CachedEvents {
+            sub_id: sub_id.to_string(),
+            events: batch_events,
+            eose: eose && events.peek().is_none(),
+        };
+        if let Err(e) = channel.send(batch) {
+            warn!(sub_id = %sub_id, error = %e, "Failed to send cached events");
+            return false;
+        }
+    }
+    eose
+}
+
+/// Send merged REQs for groupable subscriptions whose batching window has passed
+pub fn flush_groups(
+    ndb: &mut Ndb,
+    pool: &mut RelayPool,
+    subscriptions: &mut HashMap<String, Subscription>,
+    sub_id_map: &mut HashMap<u64, String>,
+    eose_tracker: &mut EoseTracker,
+    grouper: &mut SubscriptionGrouper,
+    direct: &mut DirectSubscriptions,
+    app_handle: &tauri::AppHandle,
+) {
+    for group in grouper.flush_due(Instant::now()) {
+        pool.subscribe(group.group_id.clone(), vec![group.filter]);
+        info!(group_id = %group.group_id, members = group.members.len(), "Subscribed group to relays");
+
+        let relays = eose_relay_urls(pool);
+        for member in group.members {
+            if !eose_tracker.start(&member.sub_id, relays.clone(), member.eose_timeout, member.close_on_eose) {
+                finish_eose(member.sub_id, ndb, pool, subscriptions, sub_id_map, eose_tracker, grouper, direct, app_handle);
+            }
+        }
+    }
+}
+
+/// Handle an EOSE message, fanning out group EOSEs to their member subscriptions
+pub fn handle_eose_message(
+    sub_id: &str,
+    relay_url: &str,
+    ndb: &mut Ndb,
+    pool: &mut RelayPool,
+    subscriptions: &mut HashMap<String, Subscription>,
+    sub_id_map: &mut HashMap<u64, String>,
+    eose_tracker: &mut EoseTracker,
+    grouper: &mut SubscriptionGrouper,
+    direct: &mut DirectSubscriptions,
+    app_handle: &tauri::AppHandle,
+) {
+    if !SubscriptionGrouper::is_group(sub_id) {
+        handle_relay_eose(sub_id, relay_url, ndb, pool, subscriptions, sub_id_map, eose_tracker, grouper, direct, app_handle);
+        return;
+    }
+
+    let Some(members) = grouper.members(sub_id).cloned() else {
+        return;
+    };
+    let close_group = members.iter().all(|m| eose_tracker.closes_on_eose(m));
+    for member in &members {
+        if eose_tracker.relay_eose(member, relay_url) {
+            finish_eose(member.clone(), ndb, pool, subscriptions, sub_id_map, eose_tracker, grouper, direct, app_handle);
+        }
+    }
+
+    // Once the last member closes, close_subscription CLOSEs the group on every
+    // relay; until then, close it on relays that are done
+    if close_group && grouper.members(sub_id).is_some() {
+        pool.send_to(&ClientMessage::close(sub_id.to_string()), relay_url);
+        debug!(group_id = %sub_id, relay = %relay_url, "Sent CLOSE for group after EOSE");
+    }
+}
+
+/// Handle EOSE from a single relay: CLOSE the REQ there for closeOnEose
+/// subscriptions, and finish the subscription once every relay is done.
+pub fn handle_relay_eose(
+    sub_id: &str,
+    relay_url: &str,
+    ndb: &mut Ndb,
+    pool: &mut RelayPool,
+    subscriptions: &mut HashMap<String, Subscription>,
+    sub_id_map: &mut HashMap<u64, String>,
+    eose_tracker: &mut EoseTracker,
+    grouper: &mut SubscriptionGrouper,
+    direct: &mut DirectSubscriptions,
+    app_handle: &tauri::AppHandle,
+) {
+    if eose_tracker.closes_on_eose(sub_id) {
+        pool.send_to(&ClientMessage::close(sub_id.to_string()), relay_url);
+        debug!(sub_id = %sub_id, relay = %relay_url, "Sent CLOSE after EOSE");
+    }
+    if eose_tracker.relay_eose(sub_id, relay_url) {
+        finish_eose(sub_id.to_string(), ndb, pool, subscriptions, sub_id_map, eose_tracker, grouper, direct, app_handle);
+    }
+}
+
+/// Handle CLOSED from a relay for a subscription or group. The relay won't
+/// send anything more for it, so it counts as done for EOSE coalescing.
+pub fn handle_closed_message(
+    sub_id: &str,
+    relay_url: &str,
+    reason: &str,
+    hold_eose: bool,
+    ndb: &mut Ndb,
+    pool: &mut RelayPool,
+    subscriptions: &mut HashMap<String, Subscription>,
+    sub_id_map: &mut HashMap<u64, String>,
+    eose_tracker: &mut EoseTracker,
+    grouper: &mut SubscriptionGrouper,
+    direct: &mut DirectSubscriptions,
+    app_handle: &tauri::AppHandle,
+) {
+    let prefix = message_prefix(reason);
+    warn!(sub_id = %sub_id, relay = %relay_url, reason = %reason, "Relay closed subscription");
+
+    let targets = if SubscriptionGrouper::is_group(sub_id) {
+        grouper.members(sub_id).cloned().unwrap_or_default()
+    } else {
+        vec![sub_id.to_string()]
+    };
+
+    for target in targets {
+        let _ = app_handle.emit("nostr_event", NostrResponse::Closed {
+            sub_id: target.clone(),
+            relay: relay_url.to_string(),
+            reason: reason.to_string(),
+            prefix: prefix.map(str::to_string),
+        });
+        // An auth-required: REQ is re-sent after AUTH; the relay's EOSE comes
+        // from that REQ, the AUTH rejection, or the EOSE timeout
+        if hold_eose {
+            continue;
+        }
+        if eose_tracker.relay_eose(&target, relay_url) {
+            finish_eose(target, ndb, pool, subscriptions, sub_id_map, eose_tracker, grouper, direct, app_handle);
+        }
+    }
+}
+
+/// Machine-readable prefix of an OK/CLOSED message, e.g. "auth-required" from
+/// "auth-required: we only serve members"
+pub fn message_prefix(message: &str) -> Option<&str> {
+    let (prefix, _) = message.split_once(':')?;
+    let is_token = !prefix.is_empty()
+        && prefix.bytes().all(|b| b.is_ascii_lowercase() || b == b'-');
+    is_token.then_some(prefix)
+}
+
+/// Emit the coalesced EOSE and tear down closeOnEose subscriptions
+pub fn finish_eose(
+    sub_id: String,
+    ndb: &mut Ndb,
+    pool: &mut RelayPool,
+    subscriptions: &mut HashMap<String, Subscription>,
+    sub_id_map: &mut HashMap<u64, String>,
+    eose_tracker: &mut EoseTracker,
+    grouper: &mut SubscriptionGrouper,
+    direct: &mut DirectSubscriptions,
+    app_handle: &tauri::AppHandle,
+) {
+    let _ = app_handle.emit("nostr_event", NostrResponse::Eose { sub_id: sub_id.clone() });
+    if eose_tracker.take_close_on_eose(&sub_id) {
+        close_subscription(&sub_id, ndb, pool, subscriptions, sub_id_map, grouper, direct);
+    }
+}
+
+/// Drop the nostrdb subscription and CLOSE its relay REQ. Grouped members have
+/// no REQ of their own; the shared one is closed when its last member leaves.
+fn close_subscription(
+    sub_id: &str,
+    ndb: &mut Ndb,
+    pool: &mut RelayPool,
+    subscriptions: &mut HashMap<String, Subscription>,
+    sub_id_map: &mut HashMap<u64, String>,
+    grouper: &mut SubscriptionGrouper,
+    direct: &mut DirectSubscriptions,
+) {
+    // Relay-only subscriptions have no nostrdb subscription, only this entry
+    direct.remove(sub_id);
+    if let Some(sub) = subscriptions.remove(sub_id) {
+        sub_id_map.remove(&sub.id());
+        if let Err(e) = ndb.unsubscribe(sub) {
+            warn!(sub_id = %sub_id, error = ?e, "Nostrdb unsubscribe failed");
+        }
+    }
+    if grouper.is_member(sub_id) {
+        if let Some(group_id) = grouper.remove_member(sub_id) {
+            pool.send(&ClientMessage::close(group_id.clone()));
+            debug!(group_id = %group_id, "Closed empty subscription group");
+        }
+        return;
+    }
+    pool.send(&ClientMessage::close(sub_id.to_string()));
+    debug!(sub_id = %sub_id, "Sent CLOSE to relays");
+}
+
+/// Cache query size: the subscription's cacheLimit, else the sum of the
+/// filters' own limits, else DEFAULT_QUERY_LIMIT - at most MAX_QUERY_LIMIT
+pub fn query_limit(filters: &[Filter], cache_limit: Option<u32>) -> u32 {
+    let limit = cache_limit
+        .map(u64::from)
+        .or_else(|| filters.iter().map(filter_limit).sum::<Option<u64>>())
+        .unwrap_or(DEFAULT_QUERY_LIMIT);
+    limit.clamp(1, MAX_QUERY_LIMIT) as u32
+}
+
+/// True if the filter only selects by event ID (plus an optional limit)
+fn is_id_lookup(filter: &Filter) -> bool {
+    let mut has_ids = false;
+    for field in filter.into_iter() {
+        match field {
+            nostrdb::FilterField::Ids(_) => has_ids = true,
+            nostrdb::FilterField::Limit(_) => {}
+            _ => return false,
+        }
+    }
+    has_ids
+}
+
+/// What a poll of the nostrdb subscriptions did
+pub struct PollResult {
+    pub emitted: bool,
+    /// No subscription had more notes queued than one poll takes
+    pub drained: bool,
+}
+
+/// Poll every active nostrdb subscription and emit newly matched notes
+pub fn poll_subscriptions(
+    ndb: &Ndb,
+    subscriptions: &HashMap<String, Subscription>,
+    delivered: &mut DeliveredEvents,
+    app_handle: &tauri::AppHandle,
+) -> PollResult {
+    let mut emitted = 0;
+    let mut drained = true;
+
+    for (sub_id, sub) in subscriptions.iter() {
+        let note_keys = ndb.poll_for_notes(*sub, MAX_NOTES_PER_POLL);
+        if note_keys.is_empty() {
+            continue;
+        }
+        // A full batch may leave notes queued for the next poll
+        if note_keys.len() >= MAX_NOTES_PER_POLL as usize {
+            drained = false;
+        }
+
+        let txn = match Transaction::new(ndb) {
+            Ok(txn) => txn,
+            Err(e) => {
+                warn!(sub_id = %sub_id, error = ?e, "Failed to open transaction for subscription poll");
+                continue;
+            }
+        };
+
+        for note_key in note_keys {
+            if let Ok(note) = ndb.get_note_by_key(&txn, note_key) {
+                // Already emitted from the cache query or a relay
+                if !delivered.first_delivery(sub_id, note.id()) {
+                    continue;
+                }
+                if let Some(event) = note_event(&note) {
+                    let _ = app_handle.emit("nostr_event", NostrResponse::Event {
+                        sub_id: sub_id.clone(),
+                        event,
+                        relay: None,
+                    });
+                    emitted += 1;
+                }
+            }
+        }
+    }
+
+    if emitted > 0 {
+        debug!(count = emitted, "Emitted notes from nostrdb subscriptions");
+    }
+    PollResult {
+        emitted: emitted > 0,
+        drained,
+    }
+}
+
+/// A stored note's JSON, passed on to the frontend as is
+pub fn note_event(note: &nostrdb::Note) -> Option<Box<RawValue>> {
+    note.json().ok().and_then(|json| RawValue::from_string(json).ok())
 }
 
 pub fn handle_unsubscribe(
     id: String,
+    ndb: &mut Ndb,
     pool: &mut RelayPool,
     subscriptions: &mut HashMap<String, Subscription>,
+    sub_id_map: &mut HashMap<u64, String>,
+    eose_tracker: &mut EoseTracker,
+    grouper: &mut SubscriptionGrouper,
+    direct: &mut DirectSubscriptions,
 ) {
-    subscriptions.remove(&id);
-    let close_msg = ClientMessage::close(id.clone());
-    pool.send(&close_msg);
-    debug!(sub_id = %id, "Sent CLOSE to relays");
+    eose_tracker.remove(&id);
+    close_subscription(&id, ndb, pool, subscriptions, sub_id_map, grouper, direct);
 }
 
 pub fn handle_publish(
@@ -153,6 +571,9 @@ pub fn handle_publish(
     publish_opts: Option<PublishOpts>,
     ndb: &Ndb,
     pool: &mut RelayPool,
+    publish_tracker: &mut PublishTracker,
+    outbox: &mut Outbox,
+    invalid_events: &mut InvalidEventCounter,
     app_handle: &tauri::AppHandle,
 ) {
     let destinations = publish_opts
@@ -161,25 +582,89 @@ pub fn handle_publish(
         .map(|v| v.clone())
         .unwrap_or_else(|| vec!["relay".to_string()]);
 
+    // Verify id and signature for events from untrusted sources (e.g. WebRTC peers)
+    let verify_signature = publish_opts
+        .as_ref()
+        .and_then(|opts| opts.verify_signature)
+        .unwrap_or(false);
+    if verify_signature {
+        let source = publish_opts.as_ref().and_then(|o| o.source.clone());
+        let event_id = event.get("id").and_then(|v| v.as_str()).map(str::to_string);
+
+        let result = match source.as_deref() {
+            Some(src) if invalid_events.is_limited(src) => Err("source rate-limited after repeated invalid events".to_string()),
+            _ => verify_event(&event).map_err(|e| {
+                let strikes = source.as_deref().map(|src| invalid_events.record(src));
+                warn!(pub_id = %id, source = ?source, strikes = ?strikes, error = %e, "Event failed verification");
+                e.to_string()
+            }),
+        };
+
+        if let Err(error) = result {
+            let _ = app_handle.emit("nostr_event", NostrResponse::InvalidEvent {
+                id,
+                event_id,
+                source,
+                error,
+            });
+            return;
+        }
+    } else if let Err(e) = verify_event(&event) {
+        // nostrdb validates on its ingester threads and drops bad events silently,
+        // so our own events are checked here, where the failure can be reported
+        error!(pub_id = %id, error = %e, "Invalid event");
+        let _ = app_handle.emit("nostr_event", NostrResponse::Error {
+            id: Some(id),
+            error: format!("Invalid event: {}", e),
+        });
+        return;
+    }
+
     let event_json = serde_json::to_string(&event).unwrap_or_default();
 
-    // Dispatch to local subscriptions (WebRTC events from untrusted sources)
+    // Store in nostrdb first (stores + dispatches to subs), so our own events are
+    // visible locally right away, even offline. process_event only queues the
+    // event for ingestion; it was verified above, so nostrdb won't reject it later.
+    if let Err(e) = ndb.process_event(&event_json) {
+        error!(pub_id = %id, error = ?e, "Nostrdb rejected event");
+        let _ = app_handle.emit("nostr_event", NostrResponse::Error {
+            id: Some(id),
+            error: format!("Event rejected by nostrdb: {:?}", e),
+        });
+        return;
+    }
+
+    // Local subscriptions already got it through nostrdb (WebRTC events from untrusted sources)
     if destinations.contains(&"subscriptions".to_string()) {
         if let Some(source) = publish_opts.as_ref().and_then(|o| o.source.as_ref()) {
-            debug!(source = %source, event_id = ?event.get("id"), "Dispatching to local subscriptions");
+            debug!(source = %source, event_id = ?event.get("id"), "Dispatched to local subscriptions");
         }
-
-        // Process through nostrdb (validates + stores + dispatches to subs)
-        let _ = ndb.process_event(&event_json);
     }
 
     // Publish to relays if requested
     if destinations.contains(&"relay".to_string()) {
-        match ClientMessage::event_json(event_json) {
+        match ClientMessage::event_json(event_json.clone()) {
             Ok(msg) => {
                 pool.send(&msg);
                 info!(pub_id = %id, relay_count = pool.relays.len(), "Published to relays/multicast");
-                let _ = app_handle.emit("nostr_event", NostrResponse::Published { id });
+
+                // Result is emitted once relays answer with OK (or time out)
+                let relays = connected_relay_urls(pool);
+                let event_id = event.get("id").and_then(|v| v.as_str());
+
+                // Keep it queued for every relay until each one answers with OK
+                if let Some(event_id) = event_id {
+                    outbox.enqueue(event_id, &event_json, &websocket_relay_urls(pool), &relays);
+                }
+
+                match event_id {
+                    Some(event_id) if !relays.is_empty() => {
+                        publish_tracker.start(id, event_id.to_string(), relays);
+                    }
+                    _ => {
+                        let _ = app_handle.emit("nostr_event", NostrResponse::Published { id });
+                    }
+                }
             }
             Err(e) => {
                 error!(pub_id = %id, error = ?e, "Invalid event");
@@ -194,3 +679,175 @@ pub fn handle_publish(
         let _ = app_handle.emit("nostr_event", NostrResponse::Published { id });
     }
 }
+
+// Cap on notes scanned for a local count; hitting it makes the count approximate
+const LOCAL_COUNT_LIMIT: i32 = 10_000;
+
+/// NIP-45 count: answer right away from nostrdb, then ask relays that support COUNT
+pub fn handle_count(
+    id: String,
+    filters: Vec<serde_json::Value>,
+    ndb: &Ndb,
+    pool: &mut RelayPool,
+    count_tracker: &mut CountTracker,
+    app_handle: &tauri::AppHandle,
+) {
+    let parsed_filters = match parse_filters(&filters) {
+        Ok(parsed) if !parsed.is_empty() => parsed,
+        result => {
+            let error = result.err().unwrap_or_else(|| "no filters".to_string());
+            warn!(count_id = %id, error = %error, "Invalid count filters");
+            let _ = app_handle.emit("nostr_event", NostrResponse::Error {
+                id: Some(id),
+                error,
+            });
+            return;
+        }
+    };
+
+    // Relay-only tag filters can't be counted locally
+    let local_filters: Vec<Filter> = filters
+        .iter()
+        .zip(parsed_filters)
+        .filter(|(json, _)| !has_relay_only_tags(json))
+        .map(|(_, filter)| filter)
+        .collect();
+
+    let local = if local_filters.is_empty() {
+        0
+    } else {
+        match Transaction::new(ndb) {
+            Ok(txn) => match ndb.query(&txn, &local_filters, LOCAL_COUNT_LIMIT) {
+                Ok(results) => results.len() as u64,
+                Err(e) => {
+                    warn!(count_id = %id, error = ?e, "Local count query failed");
+                    0
+                }
+            },
+            Err(e) => {
+                error!(count_id = %id, error = ?e, "Failed to create transaction");
+                0
+            }
+        }
+    };
+    let _ = app_handle.emit("nostr_event", NostrResponse::Count {
+        id: id.clone(),
+        relay: None,
+        count: local,
+        approximate: local >= LOCAL_COUNT_LIMIT as u64,
+        estimate: local,
+    });
+
+    let relays: Vec<String> = connected_relay_urls(pool)
+        .into_iter()
+        .filter(|relay| count_tracker.supports(relay))
+        .collect();
+    if relays.is_empty() {
+        let _ = app_handle.emit("nostr_event", NostrResponse::CountDone {
+            id,
+            estimate: local,
+            relays: Vec::new(),
+        });
+        return;
+    }
+
+    let msg = raw_message("COUNT", &CountTracker::query_id(&id), &filters);
+    for relay in &relays {
+        pool.send_to(&msg, relay);
+    }
+    info!(count_id = %id, local = local, relay_count = relays.len(), "Sent COUNT to relays");
+    count_tracker.start(&id, local, relays);
+}
+
+/// A REQ/COUNT-style client message carrying filters as JSON
+pub fn raw_message(kind: &str, sub_id: &str, filters: &[serde_json::Value]) -> ClientMessage {
+    let mut msg = vec![serde_json::Value::from(kind), serde_json::Value::from(sub_id)];
+    msg.extend(filters.iter().cloned());
+    ClientMessage::Raw(serde_json::Value::Array(msg).to_string())
+}
+
+/// Serve one "load older" page from nostrdb: the filters moved back to `until`
+pub fn handle_load_older(
+    id: String,
+    mut filters: Vec<serde_json::Value>,
+    until: u64,
+    limit: Option<u32>,
+    ndb: &Ndb,
+    app_handle: &tauri::AppHandle,
+) {
+    // Relay-only tag filters can't be served from nostrdb
+    filters.retain(|f| !has_relay_only_tags(f));
+    for filter in filters.iter_mut() {
+        if let Some(obj) = filter.as_object_mut() {
+            let filter_until = obj.get("until").and_then(|v| v.as_u64()).unwrap_or(u64::MAX);
+            obj.insert("until".to_string(), filter_until.min(until).into());
+        }
+    }
+
+    let parsed_filters = match parse_filters(&filters) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            warn!(page_id = %id, error = %error, "Invalid load older filters");
+            let _ = app_handle.emit("nostr_event", NostrResponse::Error { id: Some(id), error });
+            return;
+        }
+    };
+
+    let limit = query_limit(&parsed_filters, limit);
+    let mut events = Vec::new();
+    let mut next_until = None;
+    let mut more = false;
+    if !parsed_filters.is_empty() {
+        match Transaction::new(ndb) {
+            Ok(txn) => {
+                // nostrdb fills a multi-filter query filter by filter, so each filter is
+                // queried on its own to know where each one's page stopped
+                let mut notes = HashMap::new();
+                let mut cursor: Option<u64> = None;
+                for filter in &parsed_filters {
+                    match ndb.query(&txn, std::slice::from_ref(filter), limit as i32) {
+                        Ok(results) => {
+                            if results.len() >= limit as usize {
+                                let oldest = results.iter().map(|r| r.note.created_at()).min();
+                                cursor = cursor.max(oldest);
+                            }
+                            for r in results {
+                                notes.entry(*r.note.id()).or_insert(r.note);
+                            }
+                        }
+                        Err(e) => warn!(page_id = %id, error = ?e, "Load older query failed"),
+                    }
+                }
+                // A full filter may have more events at or before its oldest one, so
+                // anything older than the latest such point isn't complete yet
+                more = cursor.is_some();
+                notes.retain(|_, note| !matches!(cursor, Some(c) if note.created_at() < c));
+                let oldest = notes.values().map(|n| n.created_at()).min();
+                let newest = notes.values().map(|n| n.created_at()).max();
+                // NIP-01 until is inclusive. A full page may stop partway through
+                // its oldest second, so that second is left whole to the next page.
+                // If the entire page is one second, until can't split it: step past.
+                let boundary = cursor.filter(|_| newest != oldest);
+                next_until = match boundary {
+                    Some(second) => Some(second),
+                    None => oldest.and_then(|second| second.checked_sub(1)),
+                };
+                let mut page: Vec<_> = notes
+                    .into_values()
+                    .filter(|note| Some(note.created_at()) != boundary)
+                    .collect();
+                page.sort_by_key(|note| std::cmp::Reverse(note.created_at()));
+                events = page.iter().filter_map(note_event).collect();
+            }
+            Err(e) => error!(page_id = %id, error = ?e, "Failed to create transaction"),
+        }
+    }
+
+    debug!(page_id = %id, count = events.len(), next_until = ?next_until, "Loaded older events");
+    let _ = app_handle.emit("nostr_event", NostrResponse::OlderEvents {
+        id,
+        events,
+        until: next_until,
+        more,
+    });
+}
@@ -0,0 +1,34 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Wakes the nostr thread when there is work: a command was queued, a relay
+/// socket became readable, or a nostrdb subscription has new notes.
+#[derive(Clone, Default)]
+pub struct Wakeup {
+    inner: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl Wakeup {
+    pub fn wake(&self) {
+        let (pending, cvar) = &*self.inner;
+        *pending.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        cvar.notify_one();
+    }
+
+    /// Block until woken or the timeout passes. Wakes that arrived while the
+    /// thread was busy are not lost - they make this return immediately.
+    pub fn wait(&self, timeout: Duration) {
+        let (pending, cvar) = &*self.inner;
+        let guard = pending.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut guard, _) = cvar
+            .wait_timeout_while(guard, timeout, |pending| !*pending)
+            .unwrap_or_else(|e| e.into_inner());
+        *guard = false;
+    }
+
+    /// Closure for enostr relays and nostrdb callbacks
+    pub fn callback(&self) -> impl Fn() + Clone + Send + Sync + 'static {
+        let wakeup = self.clone();
+        move || wakeup.wake()
+    }
+}
@@ -1,14 +1,24 @@
 /* eslint-disable @typescript-eslint/no-explicit-any */
 import type NDK from "./ndk"
 import {NDKEvent} from "./ndk/events"
+import {NDKKind} from "./ndk/events/kinds"
 import type {NDKFilter} from "./ndk/subscription"
-import {invoke} from "@tauri-apps/api/core"
+import {Channel, invoke} from "@tauri-apps/api/core"
 import {listen, UnlistenFn} from "@tauri-apps/api/event"
+import type {NostrResponse} from "./tauri-bindings/NostrResponse"
+import type {RelayCount} from "./tauri-bindings/RelayCount"
+import type {CommandQueueStats} from "./tauri-bindings/CommandQueueStats"
+import type {LocalDataStats} from "./tauri-bindings/LocalDataStats"
+import type {CachedEvents} from "./tauri-bindings/CachedEvents"
+import {fetchRelayInformation} from "./ndk/relay/nip11"
+import {relayLogger} from "@/utils/relay/RelayLogger"
+import {confirm} from "@/utils/utils"
 
 interface WorkerSubscribeOpts {
   destinations?: ("cache" | "relay")[]
   closeOnEose?: boolean
   groupable?: boolean
+  cacheLimit?: number
 }
 
 interface WorkerPublishOpts {
@@ -26,11 +36,19 @@ interface WorkerMessage {
     | "close"
     | "getRelayStatus"
     | "getStats"
+    | "getMetrics"
+    | "count"
+    | "loadOlder"
     | "addRelay"
     | "removeRelay"
     | "connectRelay"
     | "disconnectRelay"
     | "reconnectDisconnected"
+    | "auth"
+    | "declineAuth"
+    | "setAuthPolicy"
+    | "setSearchRelays"
+    | "getSeenOn"
   id?: string
   filters?: NDKFilter[]
   event?: any
@@ -39,45 +57,16 @@ interface WorkerMessage {
   subscribeOpts?: WorkerSubscribeOpts
   publishOpts?: WorkerPublishOpts
   reason?: string
-}
-
-interface LocalDataStats {
-  totalEvents: number
-  eventsByKind: Record<number, number>
-  databaseSize?: string
-}
-
-interface WorkerResponse {
-  type:
-    | "ready"
-    | "event"
-    | "eose"
-    | "notice"
-    | "published"
-    | "error"
-    | "relayStatus"
-    | "stats"
-    | "relayAdded"
-    | "relayConnected"
-    | "relayDisconnected"
-  subId?: string
-  event?: any
   relay?: string
-  notice?: string
-  error?: string
-  id?: string
-  relayStatuses?: Array<{
-    url: string
-    status: number
-    stats?: {
-      attempts: number
-      success: number
-      connectedAt?: number
-    }
-  }>
-  stats?: LocalDataStats
+  policy?: RelayAuthPolicy
+  urls?: string[]
+  until?: number
+  limit?: number
+  eventId?: string
 }
 
+export type RelayAuthPolicy = "always" | "ask" | "never"
+
 /**
  * NDK transport that communicates with Tauri backend
  * Same protocol as NDKWorkerTransport but via Tauri IPC
@@ -86,6 +75,10 @@ export class NDKTauriTransport {
   public name = "tauri-transport"
   private ndk?: NDK
   private relayUrls: string[] = []
+  // NIP-50 search relays: the configured ones plus relays whose NIP-11
+  // document lists NIP-50
+  private configuredSearchRelays: string[] = []
+  private detectedSearchRelays = new Set<string>()
   private subscriptions = new Map<string, Set<(event: NDKEvent) => void>>()
   private eoseHandlers = new Map<string, Set<() => void>>()
   private publishResolvers = new Map<
@@ -97,6 +90,23 @@ export class NDKTauriTransport {
   private unlisten?: UnlistenFn
   private relayStatusCallbacks = new Map<string, (statuses: any[]) => void>()
   private statsCallbacks = new Map<string, (stats: LocalDataStats) => void>()
+  private metricsCallbacks = new Map<string, (metrics: CommandQueueStats | null) => void>()
+  private seenOnCallbacks = new Map<string, (relays: string[]) => void>()
+  private olderCallbacks = new Map<
+    string,
+    {
+      resolve: (page: {events: NDKEvent[]; until: number | null; more: boolean}) => void
+      reject: (err: Error) => void
+    }
+  >()
+  private countCallbacks = new Map<
+    string,
+    {
+      onUpdate?: (estimate: number) => void
+      resolve: (result: {estimate: number; relays: RelayCount[]}) => void
+      reject: (err: Error) => void
+    }
+  >()
 
   constructor() {
     this.setupTauri()
@@ -104,7 +114,7 @@ export class NDKTauriTransport {
 
   private async setupTauri() {
     // Listen for events from Tauri backend
-    this.unlisten = await listen<WorkerResponse>("nostr_event", (event) => {
+    this.unlisten = await listen<NostrResponse>("nostr_event", (event) => {
       this.handleResponse(event.payload)
     })
 
@@ -116,12 +126,19 @@ export class NDKTauriTransport {
     })
   }
 
-  private handleResponse(response: WorkerResponse) {
+  private handleResponse(response: NostrResponse) {
     switch (response.type) {
       case "ready":
         this.ready = true
         break
 
+      case "restarted":
+        // Backend recovered from a crash and re-sent our relays and subscriptions
+        console.warn(
+          `Tauri nostr backend restarted, restored ${response.relays} relays and ${response.subscriptions} subscriptions`
+        )
+        break
+
       case "relayStatus":
         if (response.id) {
           const callback = this.relayStatusCallbacks.get(response.id)
@@ -142,11 +159,63 @@ export class NDKTauriTransport {
         }
         break
 
+      case "cacheLimitReached":
+        console.debug(
+          `Cache query for ${response.subId} hit its limit of ${response.limit}, oldest ${response.oldest}`
+        )
+        break
+
+      case "olderEvents": {
+        const callback = this.olderCallbacks.get(response.id)
+        if (callback) {
+          callback.resolve({
+            events: response.events.map((e) => new NDKEvent(this.ndk, e as any)),
+            until: response.until,
+            more: response.more,
+          })
+          this.olderCallbacks.delete(response.id)
+        }
+        break
+      }
+
+      case "metrics": {
+        const callback = this.metricsCallbacks.get(response.id)
+        if (callback) {
+          callback(response.metrics)
+          this.metricsCallbacks.delete(response.id)
+        }
+        break
+      }
+
+      case "seenOn": {
+        const callback = this.seenOnCallbacks.get(response.id)
+        if (callback) {
+          callback(response.relays)
+          this.seenOnCallbacks.delete(response.id)
+        }
+        break
+      }
+
+      case "count": {
+        const callback = this.countCallbacks.get(response.id)
+        callback?.onUpdate?.(response.estimate)
+        break
+      }
+
+      case "countDone": {
+        const callback = this.countCallbacks.get(response.id)
+        if (callback) {
+          callback.resolve({estimate: response.estimate, relays: response.relays})
+          this.countCallbacks.delete(response.id)
+        }
+        break
+      }
+
       case "event":
         if (response.subId && response.event) {
           const handlers = this.subscriptions.get(response.subId)
           if (handlers) {
-            const event = new NDKEvent(this.ndk, response.event)
+            const event = new NDKEvent(this.ndk, response.event as any)
             handlers.forEach((handler) => handler(event))
           }
         }
@@ -161,6 +230,46 @@ export class NDKTauriTransport {
         }
         break
 
+      case "notice":
+        console.warn(`[Relay ${response.relay}] ${response.notice}`)
+        relayLogger.log("warn", response.relay, "notice", response.notice)
+        break
+
+      case "closed":
+        // Relay ended the subscription, e.g. "auth-required: ..." or "rate-limited: ..."
+        console.warn(`[Relay ${response.relay}] closed ${response.subId}: ${response.reason}`)
+        relayLogger.log(
+          "warn",
+          response.relay,
+          "closed",
+          `Subscription ${response.subId} closed: ${response.reason}`,
+          {subId: response.subId, prefix: response.prefix}
+        )
+        break
+
+      case "authRequest":
+        relayLogger.log(
+          "info",
+          response.relay,
+          "auth",
+          `Auth requested: ${response.challenge.slice(0, 16)}...`
+        )
+        this.answerAuth(response.relay, response.challenge, response.ask)
+        break
+
+      case "authResult":
+        if (response.accepted) {
+          relayLogger.log("info", response.relay, "authed", "Authenticated")
+        } else {
+          relayLogger.log(
+            "error",
+            response.relay,
+            "auth:failed",
+            `Auth failed: ${response.message}`
+          )
+        }
+        break
+
       case "published":
         if (response.id) {
           const resolver = this.publishResolvers.get(response.id)
@@ -171,12 +280,50 @@ export class NDKTauriTransport {
         }
         break
 
-      case "error":
+      case "publishResult":
         if (response.id) {
           const resolver = this.publishResolvers.get(response.id)
+          if (resolver) {
+            if (response.accepted?.length) {
+              resolver.resolve()
+            } else {
+              const reasons = (response.rejected || [])
+                .map((r) => `${r.relay}: ${r.reason}`)
+                .concat((response.timedOut || []).map((relay) => `${relay}: timeout`))
+              resolver.reject(
+                new Error(`No relay accepted the event (${reasons.join(", ")})`)
+              )
+            }
+            this.publishResolvers.delete(response.id)
+          }
+        }
+        break
+
+      case "invalidEvent":
+        if (response.id) {
+          const resolver = this.publishResolvers.get(response.id)
+          if (resolver) {
+            const from = response.source ? ` from ${response.source}` : ""
+            resolver.reject(new Error(`Invalid event${from}: ${response.error}`))
+            this.publishResolvers.delete(response.id)
+          }
+        }
+        break
+
+      case "error":
+        if (response.id) {
+          const resolver =
+            this.publishResolvers.get(response.id) ||
+            this.countCallbacks.get(response.id) ||
+            this.olderCallbacks.get(response.id)
           if (resolver) {
             resolver.reject(new Error(response.error || "Unknown error"))
             this.publishResolvers.delete(response.id)
+            this.countCallbacks.delete(response.id)
+            this.olderCallbacks.delete(response.id)
+          } else if (this.subscriptions.has(response.id)) {
+            // Backend refused the filters; an EOSE follows so fetches still settle
+            console.warn(`Subscription ${response.id} rejected: ${response.error}`)
           }
         }
         break
@@ -203,10 +350,89 @@ export class NDKTauriTransport {
     }
   }
 
+  // Sign a NIP-42 AUTH event for a relay challenge and hand it to the backend.
+  // Otherwise decline, so the backend stops waiting and can ask again later.
+  private async answerAuth(relay: string, challenge: string, ask: boolean) {
+    if (!this.ndk?.signer) {
+      await this.declineAuth(relay)
+      return
+    }
+    if (ask && !(await confirm(`${relay} asks you to authenticate. Sign in?`, "Relay authentication"))) {
+      await this.declineAuth(relay)
+      return
+    }
+
+    const event = new NDKEvent(this.ndk)
+    event.kind = NDKKind.ClientAuth
+    event.tags = [
+      ["relay", relay],
+      ["challenge", challenge],
+    ]
+    try {
+      await event.sign()
+    } catch (e) {
+      relayLogger.log("error", relay, "auth:failed", `Auth signing failed: ${e}`)
+      await this.declineAuth(relay)
+      return
+    }
+
+    await invoke("nostr_message", {
+      msg: {type: "auth", relay, event: event.rawEvent()} as WorkerMessage,
+    })
+  }
+
+  private async declineAuth(relay: string): Promise<void> {
+    await invoke("nostr_message", {
+      msg: {type: "declineAuth", relay} as WorkerMessage,
+    })
+  }
+
+  async setAuthPolicy(relay: string, policy: RelayAuthPolicy): Promise<void> {
+    await invoke("nostr_message", {
+      msg: {type: "setAuthPolicy", relay, policy} as WorkerMessage,
+    })
+  }
+
+  // Relays that get NIP-50 search subscriptions; others only serve regular REQs
+  async setSearchRelays(urls: string[]): Promise<void> {
+    await invoke("nostr_message", {
+      msg: {type: "setSearchRelays", urls} as WorkerMessage,
+    })
+  }
+
+  // Configured NIP-50 search relays, connected if they aren't already
+  async setConfiguredSearchRelays(urls: string[]): Promise<void> {
+    this.configuredSearchRelays = urls
+    for (const url of urls) {
+      await this.addRelay(url)
+    }
+    await this.updateSearchRelays()
+  }
+
+  private async updateSearchRelays(): Promise<void> {
+    const urls = new Set([...this.configuredSearchRelays, ...this.detectedSearchRelays])
+    await this.setSearchRelays([...urls])
+  }
+
+  // Use a relay for search if its NIP-11 document lists NIP-50
+  private async detectSearchSupport(url: string): Promise<void> {
+    if (this.detectedSearchRelays.has(url)) return
+    try {
+      const info = await fetchRelayInformation(url)
+      if (!info.supported_nips?.includes(50)) return
+      this.detectedSearchRelays.add(url)
+      relayLogger.log("info", url, "search", "Relay supports NIP-50 search")
+      await this.updateSearchRelays()
+    } catch (e) {
+      relayLogger.log("warn", url, "nip11:failed", `Relay information unavailable: ${e}`)
+    }
+  }
+
   async addRelay(url: string): Promise<void> {
     await invoke("nostr_message", {
       msg: {type: "addRelay", url} as WorkerMessage,
     })
+    this.detectSearchSupport(url)
   }
 
   // Transport plugin hook - intercept publishes
@@ -237,6 +463,10 @@ export class NDKTauriTransport {
         if (subscription.opts?.onEose) {
           subscription.opts.onEose()
         }
+      },
+      {
+        closeOnEose: subscription.opts?.closeOnEose,
+        groupable: subscription.opts?.groupable,
       }
     )
   }
@@ -245,7 +475,8 @@ export class NDKTauriTransport {
     subId: string,
     filters: NDKFilter[],
     onEvent: (event: NDKEvent) => void,
-    onEose: () => void
+    onEose: () => void,
+    subscribeOpts?: WorkerSubscribeOpts
   ): void {
     // Store handlers
     if (!this.subscriptions.has(subId)) {
@@ -258,16 +489,35 @@ export class NDKTauriTransport {
     }
     this.eoseHandlers.get(subId)!.add(onEose)
 
+    // Cached events arrive in batches on this channel, relay events as nostr_event
+    const onCached = new Channel<CachedEvents>()
+    onCached.onmessage = (batch) => this.handleCachedBatch(batch)
+
     // Send to backend
-    invoke("nostr_message", {
+    invoke("nostr_subscribe", {
       msg: {
         type: "subscribe",
         id: subId,
         filters,
+        subscribeOpts,
       } as WorkerMessage,
+      onCached,
     })
   }
 
+  private handleCachedBatch(batch: CachedEvents): void {
+    const handlers = this.subscriptions.get(batch.subId)
+    if (handlers) {
+      for (const rawEvent of batch.events) {
+        const event = new NDKEvent(this.ndk, rawEvent as any)
+        handlers.forEach((handler) => handler(event))
+      }
+    }
+    if (batch.eose) {
+      this.eoseHandlers.get(batch.subId)?.forEach((handler) => handler())
+    }
+  }
+
   private async unsubscribeInternal(subId: string): Promise<void> {
     this.subscriptions.delete(subId)
     this.eoseHandlers.delete(subId)
@@ -350,6 +600,9 @@ export class NDKTauriTransport {
     await invoke("nostr_message", {
       msg: {type: "removeRelay", url} as WorkerMessage,
     })
+    if (this.detectedSearchRelays.delete(url)) {
+      await this.updateSearchRelays()
+    }
   }
 
   async connectRelay(url: string): Promise<void> {
@@ -364,6 +617,84 @@ export class NDKTauriTransport {
     })
   }
 
+  /**
+   * "Load older": a page of cached events created at or before `until`, served
+   * from the local database only. Pass the returned `until` as the next `until`;
+   * pages never overlap. `more` is false once the page came back short.
+   */
+  async loadOlder(
+    filters: NDKFilter[],
+    until: number,
+    limit?: number
+  ): Promise<{events: NDKEvent[]; until: number | null; more: boolean}> {
+    const id = Math.random().toString(36).substring(7)
+
+    return new Promise((resolve, reject) => {
+      this.olderCallbacks.set(id, {resolve, reject})
+
+      invoke("nostr_message", {
+        msg: {type: "loadOlder", id, filters, until, limit} as WorkerMessage,
+      }).catch((e) => {
+        this.olderCallbacks.delete(id)
+        reject(e)
+      })
+
+      // Timeout fallback
+      setTimeout(() => {
+        if (this.olderCallbacks.has(id)) {
+          this.olderCallbacks.delete(id)
+          reject(new Error("Load older timed out"))
+        }
+      }, 10000)
+    })
+  }
+
+  /**
+   * Relays that delivered an event, while its id is in the backend's
+   * seen-id cache. Empty for events only loaded from the local cache.
+   */
+  async getSeenOn(eventId: string): Promise<string[]> {
+    const id = Math.random().toString(36).substring(7)
+
+    return new Promise((resolve) => {
+      this.seenOnCallbacks.set(id, resolve)
+
+      invoke("nostr_message", {
+        msg: {type: "getSeenOn", id, eventId} as WorkerMessage,
+      })
+
+      // Timeout fallback
+      setTimeout(() => {
+        if (this.seenOnCallbacks.has(id)) {
+          this.seenOnCallbacks.delete(id)
+          resolve([])
+        }
+      }, 1000)
+    })
+  }
+
+  /**
+   * NIP-45 count. onUpdate gets the best estimate as the local count and
+   * each relay answer arrive; the promise resolves with the final estimate.
+   */
+  async count(
+    filters: NDKFilter[],
+    onUpdate?: (estimate: number) => void
+  ): Promise<{estimate: number; relays: RelayCount[]}> {
+    const id = Math.random().toString(36).substring(7)
+
+    return new Promise((resolve, reject) => {
+      this.countCallbacks.set(id, {onUpdate, resolve, reject})
+
+      invoke("nostr_message", {
+        msg: {type: "count", id, filters} as WorkerMessage,
+      }).catch((e) => {
+        this.countCallbacks.delete(id)
+        reject(e)
+      })
+    })
+  }
+
   async getStats(): Promise<LocalDataStats> {
     const id = Math.random().toString(36).substring(7)
 
@@ -378,7 +709,37 @@ export class NDKTauriTransport {
       setTimeout(() => {
         if (this.statsCallbacks.has(id)) {
           this.statsCallbacks.delete(id)
-          resolve({totalEvents: 0, eventsByKind: {}})
+          resolve({
+            totalEvents: 0,
+            eventsByKind: {},
+            otherKinds: 0,
+            databaseSizeBytes: 0,
+            indexSizes: [],
+          })
+        }
+      }, 1000)
+    })
+  }
+
+  /**
+   * Backend command queue depth and latency. Null if the backend doesn't
+   * answer in time (e.g. the queue is backed up).
+   */
+  async getMetrics(): Promise<CommandQueueStats | null> {
+    const id = Math.random().toString(36).substring(7)
+
+    return new Promise((resolve) => {
+      this.metricsCallbacks.set(id, resolve)
+
+      invoke("nostr_message", {
+        msg: {type: "getMetrics", id} as WorkerMessage,
+      })
+
+      // Timeout fallback
+      setTimeout(() => {
+        if (this.metricsCallbacks.has(id)) {
+          this.metricsCallbacks.delete(id)
+          resolve(null)
         }
       }, 1000)
     })
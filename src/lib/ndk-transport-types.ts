@@ -1,5 +1,8 @@
 import type {NDKFilter} from "./ndk/subscription"
 import type {SettingsState} from "../stores/settings"
+import type {LocalDataStats} from "./tauri-bindings/LocalDataStats"
+
+export type {LocalDataStats}
 
 export interface WorkerSubscribeOpts {
   destinations?: ("cache" | "relay")[]
@@ -14,12 +17,6 @@ export interface WorkerPublishOpts {
   source?: string
 }
 
-export interface LocalDataStats {
-  totalEvents: number
-  eventsByKind: Record<number, number>
-  databaseSize?: string
-}
-
 export type SearchResult = {
   name: string
   pubKey: string
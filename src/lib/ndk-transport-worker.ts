@@ -551,7 +551,13 @@ export class NDKWorkerTransport {
       setTimeout(() => {
         if (this.statsCallbacks.has(id)) {
           this.statsCallbacks.delete(id)
-          resolve({totalEvents: 0, eventsByKind: {}})
+          resolve({
+            totalEvents: 0,
+            eventsByKind: {},
+            otherKinds: 0,
+            databaseSizeBytes: 0,
+            indexSizes: [],
+          })
         }
       }, 1000)
     })
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { JsonValue } from "./serde_json/JsonValue";
+
+/**
+ * Cached events for one subscription, sent over its nostr_subscribe channel
+ */
+export type CachedEvents = { subId: string, events: Array<JsonValue>, eose: boolean, };
@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { IndexStats } from "./IndexStats";
+
+export type LocalDataStats = { totalEvents: number, eventsByKind: { [key in number]?: number }, 
+/**
+ * Events whose kind has no dedicated nostrdb counter
+ */
+otherKinds: number, 
+/**
+ * Size of the nostrdb data file in bytes
+ */
+databaseSizeBytes: number, indexSizes: Array<IndexStats>, };
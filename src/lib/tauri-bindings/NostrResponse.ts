@@ -0,0 +1,21 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { CommandQueueStats } from "./CommandQueueStats";
+import type { JsonValue } from "./serde_json/JsonValue";
+import type { LocalDataStats } from "./LocalDataStats";
+import type { RelayCount } from "./RelayCount";
+import type { RelayRejection } from "./RelayRejection";
+import type { RelayStatusInfo } from "./RelayStatusInfo";
+
+export type NostrResponse = { "type": "ready" } | { "type": "event", subId: string, 
+/**
+ * The event JSON as received or as stored by nostrdb, never re-parsed
+ */
+event: JsonValue, relay: string | null, } | { "type": "eose", subId: string, } | { "type": "published", id: string, } | { "type": "publishResult", id: string, eventId: string, accepted: Array<string>, rejected: Array<RelayRejection>, timedOut: Array<string>, } | { "type": "error", id: string | null, error: string, } | { "type": "invalidEvent", id: string, eventId: string | null, source: string | null, error: string, } | { "type": "relayStatus", id: string, relayStatuses: Array<RelayStatusInfo>, } | { "type": "stats", id: string, stats: LocalDataStats, } | { "type": "metrics", id: string, metrics: CommandQueueStats, } | { "type": "restarted", relays: number, subscriptions: number, } | { "type": "relayAdded", relay: string, } | { "type": "relayRemoved", relay: string, } | { "type": "relayConnected", relay: string, } | { "type": "relayDisconnected", relay: string, } | { "type": "relayError", relay: string, error: string, } | { "type": "notice", relay: string, notice: string, } | { "type": "closed", subId: string, relay: string, reason: string, prefix: string | null, } | { "type": "authRequest", relay: string, challenge: string, ask: boolean, } | { "type": "authResult", relay: string, accepted: boolean, message: string, } | { "type": "count", id: string, relay: string | null, count: number, approximate: boolean, estimate: number, } | { "type": "countDone", id: string, estimate: number, relays: Array<RelayCount>, } | { "type": "cacheLimitReached", subId: string, limit: number, oldest: number | null, } | { "type": "olderEvents", id: string, events: Array<JsonValue>, 
+/**
+ * The next page's until. Never repeats events from this page.
+ */
+until: number | null, 
+/**
+ * The page was full, so there may be more
+ */
+more: boolean, } | { "type": "seenOn", id: string, eventId: string, relays: Array<string>, };
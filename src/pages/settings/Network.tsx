@@ -10,6 +10,8 @@ import {PeerConnectionList} from "@/shared/components/connection/PeerConnectionL
 import {RelayLogViewer} from "@/shared/components/connection/RelayLogViewer"
 import {peerConnectionManager} from "@/utils/chat/webrtc/PeerConnectionManager"
 import {getP2PStats, resetP2PStats} from "@/utils/chat/webrtc/p2pNostr"
+import {isTauri} from "@/utils/utils"
+import {RiCloseLine} from "@remixicon/react"
 
 export function Network() {
   const {
@@ -22,6 +24,7 @@ export function Network() {
   } = useUserStore()
   const {showRelayIndicator, setShowRelayIndicator} = useUIStore()
   const {network, updateNetwork} = useSettingsStore()
+  const [searchRelay, setSearchRelay] = useState("")
 
   const [p2pStats, setP2pStats] = useState({
     eventsSent: 0,
@@ -106,6 +109,21 @@ export function Network() {
     window.location.reload()
   }
 
+  const handleAddSearchRelay = () => {
+    let url = searchRelay.trim()
+    if (!url) return
+    if (!url.startsWith("wss://") && !url.startsWith("ws://")) {
+      url = `wss://${url}`
+    }
+    if (!url.endsWith("/")) {
+      url = url + "/"
+    }
+    if (!network.searchRelays.includes(url)) {
+      updateNetwork({searchRelays: [...network.searchRelays, url]})
+    }
+    setSearchRelay("")
+  }
+
   const hasDefaultRelays = useMemo(() => {
     const enabledUrls = relayConfigs?.filter((c) => !c.disabled).map((c) => c.url) || []
     return (
@@ -205,6 +223,52 @@ export function Network() {
             </SettingsGroupItem>
           </SettingsGroup>
 
+          {isTauri() && (
+            <SettingsGroup title="Search Relays">
+              <SettingsGroupItem isLast>
+                <div className="flex flex-col space-y-2">
+                  <span className="text-sm text-base-content/60">
+                    Relays used for NIP-50 search, besides connected relays that advertise
+                    NIP-50 support
+                  </span>
+                  {network.searchRelays.map((url) => (
+                    <div key={url} className="flex justify-between items-center">
+                      <span className="text-sm break-all">{url}</span>
+                      <button
+                        className="btn btn-sm btn-ghost px-2"
+                        onClick={() =>
+                          updateNetwork({
+                            searchRelays: network.searchRelays.filter((u) => u !== url),
+                          })
+                        }
+                      >
+                        <RiCloseLine className="w-4 h-4" />
+                      </button>
+                    </div>
+                  ))}
+                  <div className="flex gap-1 items-center">
+                    <input
+                      type="text"
+                      placeholder="wss://search.example.com"
+                      value={searchRelay}
+                      onChange={(e) => setSearchRelay(e.target.value)}
+                      onKeyDown={(e) => {
+                        if (e.key === "Enter") handleAddSearchRelay()
+                      }}
+                      className="input input-sm flex-1 text-sm"
+                    />
+                    <button
+                      onClick={handleAddSearchRelay}
+                      className="btn btn-sm btn-primary px-2"
+                    >
+                      Add
+                    </button>
+                  </div>
+                </div>
+              </SettingsGroupItem>
+            </SettingsGroup>
+          )}
+
           <SettingsGroup title="WebRTC Peer Connections">
             <SettingsGroupItem>
               <div className="flex justify-between items-center">
@@ -49,23 +49,17 @@ export function LocalData() {
 
       // Sort kinds by count
       const sortedKinds = Object.entries(stats.eventsByKind)
-        .sort(([, a], [, b]) => b - a)
+        .sort(([, a], [, b]) => (b ?? 0) - (a ?? 0))
         .reduce(
           (acc, [kind, count]) => {
-            acc[parseInt(kind)] = count
+            acc[parseInt(kind)] = count ?? 0
             return acc
           },
           {} as Record<number, number>
         )
 
-      // Total storage estimate
-      let dbSize: string | undefined
-      if ("estimate" in navigator.storage) {
-        const estimate = await navigator.storage.estimate()
-        if (estimate.usage) {
-          dbSize = formatBytes(estimate.usage)
-        }
-      }
+      // Total storage: nostrdb file size on Tauri, the browser's storage estimate on web
+      const dbSize = stats.databaseSizeBytes ? formatBytes(stats.databaseSizeBytes) : undefined
 
       setStats({
         totalEvents: stats.totalEvents,
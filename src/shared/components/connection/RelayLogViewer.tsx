@@ -57,6 +57,7 @@ export function RelayLogViewer() {
       case "flapping":
       case "delayed-connect":
       case "notice":
+      case "closed":
         return "badge-warning"
       case "auth:failed":
       case "publish:failed":
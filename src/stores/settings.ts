@@ -57,6 +57,8 @@ export interface SettingsState {
     webrtcCallsEnabled: boolean
     webrtcFileReceivingEnabled: boolean
     negentropyEnabled: boolean
+    // NIP-50 search relays, used besides relays that advertise NIP-50 in NIP-11
+    searchRelays: string[]
   }
   // Desktop settings
   desktop: {
@@ -83,6 +85,8 @@ export interface SettingsState {
   updateLegal: (settings: Partial<SettingsState["legal"]>) => void
 }
 
+export const DEFAULT_SEARCH_RELAYS = ["wss://relay.nostr.band/"]
+
 export const useSettingsStore = create<SettingsState>()(
   persist(
     (set) => ({
@@ -134,6 +138,7 @@ export const useSettingsStore = create<SettingsState>()(
         webrtcCallsEnabled: true,
         webrtcFileReceivingEnabled: true,
         negentropyEnabled: false,
+        searchRelays: DEFAULT_SEARCH_RELAYS,
       },
       desktop: {
         startOnBoot: true,
@@ -209,6 +214,7 @@ export const useSettingsStore = create<SettingsState>()(
             webrtcCallsEnabled: true,
             webrtcFileReceivingEnabled: true,
             negentropyEnabled: false,
+            searchRelays: DEFAULT_SEARCH_RELAYS,
           }
         }
         // Migrate network settings without new fields
@@ -241,6 +247,9 @@ export const useSettingsStore = create<SettingsState>()(
           if (state.network.negentropyEnabled === undefined) {
             state.network.negentropyEnabled = false
           }
+          if (state.network.searchRelays === undefined) {
+            state.network.searchRelays = DEFAULT_SEARCH_RELAYS
+          }
         }
       },
     }
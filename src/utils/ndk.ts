@@ -130,6 +130,9 @@ async function performInit(opts?: NDKConstructorParams) {
   // Connect it synchronously (registers itself and sends init)
   const transport = isTauri() ? tauriTransport : workerTransport
   transport!.connect(ndkInstance!, relays) // Don't await - queues messages
+  tauriTransport?.setConfiguredSearchRelays(
+    useSettingsStore.getState().network.searchRelays
+  )
 
   const ndk = ndkInstance!
 
@@ -239,6 +242,15 @@ function watchLocalSettings(instance: NDK) {
       instance.p2pOnlyMode = state.network.p2pOnlyMode
       log("P2P-only mode:", state.network.p2pOnlyMode ? "enabled" : "disabled")
     }
+    if (tauriTransport && state.network.searchRelays !== prevState.network.searchRelays) {
+      const userRelays = useUserStore.getState().relayConfigs?.map((c) => c.url) || []
+      for (const url of prevState.network.searchRelays) {
+        if (!state.network.searchRelays.includes(url) && !userRelays.includes(url)) {
+          tauriTransport.removeRelay(url)
+        }
+      }
+      tauriTransport.setConfiguredSearchRelays(state.network.searchRelays)
+    }
   })
 
   useUserStore.subscribe((state, prevState) => {
@@ -92,7 +92,7 @@ class RelayLogger extends EventEmitter<{
     relay.removeAllListeners()
   }
 
-  private log(
+  log(
     level: RelayLogLevel,
     relayUrl: string,
     event: string,
@@ -24,6 +24,7 @@ import {NDKSubscriptionCacheUsage, type NDKFilter} from "../lib/ndk/subscription
 import {NDKRelay} from "../lib/ndk/relay"
 import NDKCacheAdapterDexie, {db} from "../lib/ndk-cache"
 import type {
+  LocalDataStats,
   WorkerMessage,
   WorkerResponse,
   WorkerSubscribeOpts,
@@ -502,16 +503,21 @@ function handleBrowserOnline() {
   handleReconnectDisconnected("Browser came online")
 }
 
+const EMPTY_STATS: LocalDataStats = {
+  totalEvents: 0,
+  eventsByKind: {},
+  otherKinds: 0,
+  databaseSizeBytes: 0,
+  indexSizes: [],
+}
+
 async function handleGetStats(id: string) {
   try {
     if (!db) {
       self.postMessage({
         type: "stats",
         id,
-        stats: {
-          totalEvents: 0,
-          eventsByKind: {},
-        },
+        stats: EMPTY_STATS,
       } as WorkerResponse)
       return
     }
@@ -532,12 +538,18 @@ async function handleGetStats(id: string) {
       })
     )
 
+    // Dexie has no per-database size; use the origin's storage estimate
+    const estimate = await navigator.storage?.estimate?.().catch(() => undefined)
+
     self.postMessage({
       type: "stats",
       id,
       stats: {
         totalEvents,
         eventsByKind,
+        otherKinds: 0,
+        databaseSizeBytes: estimate?.usage ?? 0,
+        indexSizes: [],
       },
     } as WorkerResponse)
   } catch (err) {
@@ -545,10 +557,7 @@ async function handleGetStats(id: string) {
     self.postMessage({
       type: "stats",
       id,
-      stats: {
-        totalEvents: 0,
-        eventsByKind: {},
-      },
+      stats: EMPTY_STATS,
     } as WorkerResponse)
   }
 }